@@ -0,0 +1,604 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, Read, Write},
+    os::unix::{
+        fs::{DirBuilderExt, FileTypeExt, MetadataExt, PermissionsExt},
+        io::AsRawFd,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::crypto::KeyHandle;
+use crate::keyfile::Keyfile;
+use crate::secret::{SecretBytes, SecretString};
+use crate::storage;
+use zeroize::Zeroizing;
+
+/// Environment variable holding the agent socket path. When it is set the
+/// CLI unlocks and saves the wallet through the agent.
+pub const SOCK_ENV: &str = "WALLET_AGENT_SOCK";
+
+/// How long the agent keeps keys after the last request.
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+/// Clients wait this long for an answer; unlocking runs the full KDF.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Connections that stall are dropped after this long.
+const SERVER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest request or response accepted, which bounds the wallet size too.
+const MAX_MESSAGE_LEN: usize = 64 * 1024 * 1024;
+
+/// One request per connection, sent as a line of JSON.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Request {
+    /// Decrypt the wallet with a cached key.
+    Load { path: String },
+    /// Derive and cache the key for a wallet, returning its contents.
+    Unlock {
+        path: String,
+        password: SecretString,
+        #[serde(default)]
+        keyfile: Option<Keyfile>,
+    },
+    /// Encrypt a wallet document with the cached key and write the file.
+    Store { path: String, data: SecretBytes },
+    /// Forget all cached keys.
+    Lock,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Response {
+    Ok,
+    Plaintext {
+        data: SecretBytes,
+    },
+    /// No key is cached for the wallet.
+    Locked,
+    /// Unlock failed because the password or keyfile is wrong.
+    WrongPassword {
+        message: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// Default socket: `$XDG_RUNTIME_DIR/wallet-agent.sock`, or a private
+/// directory in the temp dir.
+pub fn default_socket_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("wallet-agent.sock"),
+        None => env::temp_dir()
+            .join(format!("wallet-agent-{}", current_uid()))
+            .join("agent.sock"),
+    }
+}
+
+fn current_uid() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+struct State {
+    /// Derived keys, which [`KeyHandle`] keeps locked in RAM.
+    keys: HashMap<PathBuf, KeyHandle>,
+    last_used: Instant,
+}
+
+/// The agent daemon: answers [`Request`]s on a Unix socket that only the
+/// current user can reach.
+pub struct Agent {
+    listener: UnixListener,
+    socket: PathBuf,
+    idle_timeout: Duration,
+    state: Arc<Mutex<State>>,
+}
+
+impl Agent {
+    /// Creates the socket, inside a directory only the current user can
+    /// access, and makes the socket itself mode 0600.
+    pub fn bind(socket: impl Into<PathBuf>, idle_timeout: Duration) -> io::Result<Self> {
+        let socket = socket.into();
+        prepare_socket_dir(&socket)?;
+        match fs::symlink_metadata(&socket) {
+            Ok(meta) if meta.file_type().is_socket() => {
+                if UnixStream::connect(&socket).is_ok() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        format!("An agent is already listening on {}", socket.display()),
+                    ));
+                }
+                fs::remove_file(&socket)?;
+            }
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", socket.display()),
+                ));
+            }
+            Err(_) => {}
+        }
+
+        let listener = UnixListener::bind(&socket)?;
+        fs::set_permissions(&socket, fs::Permissions::from_mode(0o600))?;
+        Ok(Agent {
+            listener,
+            socket,
+            idle_timeout,
+            state: Arc::new(Mutex::new(State {
+                keys: HashMap::new(),
+                last_used: Instant::now(),
+            })),
+        })
+    }
+
+    /// Path of the listening socket.
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+
+    /// Serves requests until the process is killed, each connection on its
+    /// own thread, forgetting all keys once no request has arrived for the
+    /// idle timeout.
+    pub fn run(self) -> io::Result<()> {
+        let state = Arc::clone(&self.state);
+        let idle_timeout = self.idle_timeout;
+        thread::spawn(move || {
+            loop {
+                thread::sleep((idle_timeout / 2).min(Duration::from_secs(1)));
+                let mut state = state.lock().unwrap();
+                if !state.keys.is_empty() && state.last_used.elapsed() >= idle_timeout {
+                    state.keys.clear();
+                }
+            }
+        });
+
+        for stream in self.listener.incoming() {
+            let state = Arc::clone(&self.state);
+            let result = stream.map(|stream| {
+                thread::spawn(move || {
+                    if let Err(e) = serve_connection(&state, stream) {
+                        eprintln!("wallet agent: {}", e);
+                    }
+                })
+            });
+            if let Err(e) = result {
+                eprintln!("wallet agent: {}", e);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn serve_connection(state: &Mutex<State>, stream: UnixStream) -> io::Result<()> {
+    let uid = peer_uid(&stream)?;
+    if uid != current_uid() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("Refused connection from uid {}", uid),
+        ));
+    }
+    stream.set_read_timeout(Some(SERVER_TIMEOUT))?;
+    stream.set_write_timeout(Some(SERVER_TIMEOUT))?;
+
+    let line = read_message(&stream)?;
+    let response = match serde_json::from_slice(&line.0) {
+        Ok(request) => {
+            state.lock().unwrap().last_used = Instant::now();
+            handle(state, request)
+        }
+        Err(e) => Response::Error {
+            message: format!("Malformed request: {}", e),
+        },
+    };
+    write_message(&stream, &response)
+}
+
+/// Answers one request. Load and Store only run AES with a cached key and
+/// hold the lock throughout; Unlock derives the key without it, so a slow
+/// KDF never holds up other clients.
+fn handle(state: &Mutex<State>, request: Request) -> Response {
+    let error = |message: String| Response::Error { message };
+    match request {
+        Request::Load { path } => {
+            let Ok(path) = fs::canonicalize(&path) else {
+                return Response::Locked;
+            };
+            let mut state = state.lock().unwrap();
+            let Some(cached) = state.keys.get_mut(&path) else {
+                return Response::Locked;
+            };
+            let decrypted = fs::read(&path).and_then(|data| cached.decrypt(&data));
+            match decrypted {
+                Ok(data) => Response::Plaintext { data },
+                // The file was re-keyed elsewhere; the cached key is stale.
+                Err(_) => {
+                    state.keys.remove(&path);
+                    Response::Locked
+                }
+            }
+        }
+        Request::Unlock {
+            path,
+            password,
+            keyfile,
+        } => {
+            let unlocked = fs::canonicalize(&path).and_then(|path| {
+                let data = fs::read(&path)?;
+                let (key, plaintext) = KeyHandle::open(&data, &password, keyfile.as_ref())?;
+                let mut state = state.lock().unwrap();
+                // Cached keys only decrypt files with key slots.
+                if key.needs_upgrade() {
+                    storage::write_atomic(&path, &key.encrypt(&plaintext))?;
+                }
+                state.keys.insert(path, key);
+                Ok(plaintext)
+            });
+            match unlocked {
+                Ok(data) => Response::Plaintext { data },
+                Err(e) if e.kind() == io::ErrorKind::InvalidInput && keyfile.is_some() => {
+                    Response::WrongPassword {
+                        message: "Incorrect password or keyfile, or corrupted wallet file"
+                            .to_string(),
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::InvalidInput => Response::WrongPassword {
+                    message: "Incorrect password or corrupted wallet file".to_string(),
+                },
+                Err(e) => error(e.to_string()),
+            }
+        }
+        Request::Store { path, data } => {
+            let Ok(path) = fs::canonicalize(&path) else {
+                return Response::Locked;
+            };
+            let mut state = state.lock().unwrap();
+            let Some(cached) = state.keys.get_mut(&path) else {
+                return Response::Locked;
+            };
+            // Never overwrite a file that was re-keyed since it was unlocked.
+            let current = fs::read(&path).and_then(|current| cached.decrypt(&current));
+            if current.is_err() {
+                state.keys.remove(&path);
+                return error("Wallet file changed since it was unlocked".to_string());
+            }
+            let encrypted = cached.encrypt(&data);
+            match storage::write_atomic(&path, &encrypted) {
+                Ok(()) => Response::Ok,
+                Err(e) => error(format!("Cannot write wallet file: {}", e)),
+            }
+        }
+        Request::Lock => {
+            state.lock().unwrap().keys.clear();
+            Response::Ok
+        }
+    }
+}
+
+/// Creates the socket's directory with mode 0700, or checks that an existing
+/// one belongs to the current user and is closed to everyone else.
+fn prepare_socket_dir(socket: &Path) -> io::Result<()> {
+    let Some(dir) = socket.parent().filter(|dir| !dir.as_os_str().is_empty()) else {
+        return Ok(());
+    };
+    if !dir.exists() {
+        return fs::DirBuilder::new().mode(0o700).create(dir);
+    }
+    let meta = fs::metadata(dir)?;
+    if meta.uid() != current_uid() || meta.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "Socket directory {} must be owned by you with mode 700",
+                dir.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// User id of the process on the other end of `stream`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &UnixStream) -> io::Result<u32> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut cred as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(cred.uid)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peer_uid(stream: &UnixStream) -> io::Result<u32> {
+    let (mut uid, mut gid) = (0, 0);
+    if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(uid)
+}
+
+/// A byte buffer that wipes every allocation it outgrows, and itself when
+/// dropped. Messages carry passwords and decrypted wallets.
+#[derive(Default)]
+struct MessageBuffer(Zeroizing<Vec<u8>>);
+
+impl Write for MessageBuffer {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        if self.0.capacity() - self.0.len() < bytes.len() {
+            let capacity = (self.0.len() + bytes.len()).next_power_of_two();
+            let mut grown = Zeroizing::new(Vec::with_capacity(capacity));
+            grown.extend_from_slice(&self.0);
+            self.0 = grown;
+        }
+        self.0.extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn write_message<T: Serialize>(mut stream: &UnixStream, message: &T) -> io::Result<()> {
+    let mut line = MessageBuffer::default();
+    serde_json::to_writer(&mut line, message).map_err(io::Error::other)?;
+    line.write_all(b"\n")?;
+    stream.write_all(&line.0)
+}
+
+/// Reads one newline-terminated message of at most [`MAX_MESSAGE_LEN`] bytes.
+fn read_message(mut stream: &UnixStream) -> io::Result<MessageBuffer> {
+    let mut line = MessageBuffer::default();
+    let mut chunk = Zeroizing::new([0u8; 4096]);
+    loop {
+        let read = match stream.read(&mut chunk[..]) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            result => result?,
+        };
+        let chunk = &chunk[..read];
+        let end = chunk.iter().position(|&b| b == b'\n');
+        line.write_all(&chunk[..end.unwrap_or(read)])?;
+        if line.0.len() > MAX_MESSAGE_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Message exceeds {} bytes", MAX_MESSAGE_LEN),
+            ));
+        }
+        if read == 0 || end.is_some() {
+            return Ok(line);
+        }
+    }
+}
+
+/// Talks to a running [`Agent`].
+pub struct Client {
+    socket: PathBuf,
+}
+
+impl Client {
+    pub fn new(socket: impl Into<PathBuf>) -> Self {
+        Client {
+            socket: socket.into(),
+        }
+    }
+
+    /// A client for the socket named by [`SOCK_ENV`], if it is set.
+    pub fn from_env() -> Option<Self> {
+        env::var_os(SOCK_ENV)
+            .filter(|socket| !socket.is_empty())
+            .map(Client::new)
+    }
+
+    fn request(&self, request: &Request) -> io::Result<Response> {
+        let stream = UnixStream::connect(&self.socket).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Cannot reach agent at {}: {}", self.socket.display(), e),
+            )
+        })?;
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        write_message(&stream, request)?;
+
+        let line = read_message(&stream)?;
+        serde_json::from_slice(&line.0).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid agent response: {}", e),
+            )
+        })
+    }
+
+    /// Returns the decrypted wallet document, or `None` if the agent holds
+    /// no key for it.
+    pub fn load(&self, wallet_path: &str) -> io::Result<Option<SecretBytes>> {
+        match self.request(&Request::Load {
+            path: wallet_path.to_string(),
+        })? {
+            Response::Plaintext { data } => Ok(Some(data)),
+            Response::Locked => Ok(None),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Has the agent derive and keep the key, returning the wallet document.
+    /// A wrong password is reported as [`io::ErrorKind::InvalidInput`].
+    pub fn unlock(
+        &self,
+        wallet_path: &str,
+        password: &str,
+        keyfile: Option<&Keyfile>,
+    ) -> io::Result<SecretBytes> {
+        match self.request(&Request::Unlock {
+            path: wallet_path.to_string(),
+            password: password.into(),
+            keyfile: keyfile.cloned(),
+        })? {
+            Response::Plaintext { data } => Ok(data),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Has the agent encrypt `plaintext` with the cached key and write it.
+    pub fn store(&self, wallet_path: &str, plaintext: &[u8]) -> io::Result<()> {
+        match self.request(&Request::Store {
+            path: wallet_path.to_string(),
+            data: plaintext.to_vec().into(),
+        })? {
+            Response::Ok => Ok(()),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Makes the agent forget every cached key.
+    pub fn lock(&self) -> io::Result<()> {
+        match self.request(&Request::Lock)? {
+            Response::Ok => Ok(()),
+            other => Err(unexpected(other)),
+        }
+    }
+}
+
+fn unexpected(response: Response) -> io::Error {
+    match response {
+        Response::WrongPassword { message } => io::Error::new(io::ErrorKind::InvalidInput, message),
+        Response::Error { message } => io::Error::other(message),
+        Response::Locked => io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "The agent holds no key for this wallet",
+        ),
+        other => io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unexpected agent response: {:?}", other),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto;
+
+    const TEST_KDF: crate::header::Kdf = crate::header::Kdf::Argon2id {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    fn start_agent(name: &str, idle_timeout: Duration) -> (Client, PathBuf) {
+        let dir = env::temp_dir().join(format!("wallet-agent-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let agent = Agent::bind(dir.join("agent.sock"), idle_timeout).unwrap();
+        let client = Client::new(agent.socket());
+        thread::spawn(move || agent.run());
+
+        let wallet = dir.join("wallet.txt");
+        let data = crypto::encrypt_with(b"{}", "hunter2", &TEST_KDF).unwrap();
+        fs::write(&wallet, data).unwrap();
+        (client, dir)
+    }
+
+    #[test]
+    fn test_unlock_store_and_lock() {
+        let (client, dir) = start_agent("cycle", DEFAULT_IDLE_TIMEOUT);
+        let wallet = dir.join("wallet.txt");
+        let wallet = wallet.to_str().unwrap();
+
+        assert_eq!(fs::metadata(dir.as_path()).unwrap().mode() & 0o777, 0o700);
+        assert_eq!(client.load(wallet).unwrap(), None);
+        assert_eq!(
+            client.unlock(wallet, "wrong", None).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        // Only a wrong password looks like one; other failures must not be retried.
+        let missing = dir.join("missing.txt");
+        assert_ne!(
+            client
+                .unlock(missing.to_str().unwrap(), "hunter2", None)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(client.unlock(wallet, "hunter2", None).unwrap(), b"{}");
+
+        client.store(wallet, b"{\"a\":1}").unwrap();
+        assert_eq!(client.load(wallet).unwrap().unwrap(), b"{\"a\":1}");
+        // Other processes still open the file with the password.
+        assert_eq!(
+            crypto::decrypt(&fs::read(wallet).unwrap(), "hunter2").unwrap(),
+            b"{\"a\":1}"
+        );
+
+        client.lock().unwrap();
+        assert_eq!(client.load(wallet).unwrap(), None);
+        assert!(client.store(wallet, b"{}").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_messages_are_bounded() {
+        let (reader, writer) = UnixStream::pair().unwrap();
+        write_message(&writer, &Response::Locked).unwrap();
+        let line = read_message(&reader).unwrap();
+        assert_eq!(&line.0[..], br#"{"status":"locked"}"#);
+
+        let flood = thread::spawn(move || {
+            let _ = (&writer).write_all(&vec![b'x'; MAX_MESSAGE_LEN + 1]);
+        });
+        let result = read_message(&reader);
+        assert!(matches!(result, Err(e) if e.kind() == io::ErrorKind::InvalidData));
+        drop(reader);
+        flood.join().unwrap();
+    }
+
+    #[test]
+    fn test_stalled_connection_does_not_block_others() {
+        let (client, dir) = start_agent("stalled", DEFAULT_IDLE_TIMEOUT);
+        let wallet = dir.join("wallet.txt");
+
+        // Sends nothing, so its thread waits for SERVER_TIMEOUT.
+        let _stalled = UnixStream::connect(dir.join("agent.sock")).unwrap();
+        let started = Instant::now();
+        assert_eq!(client.load(wallet.to_str().unwrap()).unwrap(), None);
+        assert!(started.elapsed() < SERVER_TIMEOUT / 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_keys_expire_and_follow_rekeys() {
+        let (client, dir) = start_agent("idle", Duration::from_millis(200));
+        let wallet = dir.join("wallet.txt");
+        let wallet = wallet.to_str().unwrap();
+
+        client.unlock(wallet, "hunter2", None).unwrap();
+        thread::sleep(Duration::from_millis(600));
+        assert_eq!(client.load(wallet).unwrap(), None);
+
+        client.unlock(wallet, "hunter2", None).unwrap();
+        let rekeyed = crypto::encrypt_with(b"{}", "new password", &TEST_KDF).unwrap();
+        fs::write(wallet, rekeyed).unwrap();
+        assert!(client.store(wallet, b"{}").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,249 @@
+use serde::Serialize;
+use std::{collections::HashMap, fmt};
+
+use crate::entry::{self, Entry};
+use crate::strength::WEAK_SCORE;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Settings for [`audit`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditOptions {
+    /// Entries not modified for more days than this are reported as old.
+    pub max_age_days: u64,
+    /// Secrets scoring below this (0-4) are reported as weak.
+    pub min_score: u8,
+    /// Current time in seconds since the Unix epoch.
+    pub now: u64,
+}
+
+impl Default for AuditOptions {
+    fn default() -> Self {
+        AuditOptions {
+            max_age_days: 365,
+            min_score: WEAK_SCORE,
+            now: entry::now(),
+        }
+    }
+}
+
+/// A single problem found by [`audit`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Finding {
+    /// Several entries share the same secret.
+    Reused { entries: Vec<String> },
+    /// The secret is easy to guess.
+    Weak {
+        entry: String,
+        score: u8,
+        warning: Option<String>,
+    },
+    /// The secret has not been changed for a long time.
+    Old { entry: String, age_days: u64 },
+    /// Two entry names differ only slightly and may be duplicates.
+    NearDuplicate { entries: Vec<String> },
+    /// The secret is empty or only whitespace.
+    Empty { entry: String },
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Finding::Reused { entries } => {
+                write!(f, "reused secret: {}", entries.join(", "))
+            }
+            Finding::Weak {
+                entry,
+                score,
+                warning,
+            } => {
+                write!(f, "weak secret: {} (score {}/4)", entry, score)?;
+                if let Some(warning) = warning {
+                    write!(f, ": {}", warning)?;
+                }
+                Ok(())
+            }
+            Finding::Old { entry, age_days } => {
+                write!(f, "old secret: {} (unchanged for {} days)", entry, age_days)
+            }
+            Finding::NearDuplicate { entries } => {
+                write!(f, "near-duplicate names: {}", entries.join(", "))
+            }
+            Finding::Empty { entry } => write!(f, "empty value: {}", entry),
+        }
+    }
+}
+
+/// Result of auditing a wallet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AuditReport {
+    pub entries_checked: usize,
+    pub findings: Vec<Finding>,
+}
+
+/// Checks entries for reused, weak, old and empty secrets and near-duplicate
+/// names. Findings are sorted by kind and entry name.
+pub fn audit<'a>(
+    entries: impl IntoIterator<Item = &'a Entry>,
+    options: &AuditOptions,
+) -> AuditReport {
+    let mut entries: Vec<&Entry> = entries.into_iter().collect();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut findings = Vec::new();
+
+    let mut by_secret: HashMap<&str, Vec<String>> = HashMap::new();
+    for entry in &entries {
+        if !entry.secret.trim().is_empty() {
+            by_secret
+                .entry(&*entry.secret)
+                .or_default()
+                .push(entry.name.clone());
+        }
+    }
+    let mut reused: Vec<Vec<String>> = by_secret
+        .into_values()
+        .filter(|names| names.len() > 1)
+        .collect();
+    reused.sort();
+    findings.extend(
+        reused
+            .into_iter()
+            .map(|entries| Finding::Reused { entries }),
+    );
+
+    for entry in &entries {
+        // Entries that only hold an OTP seed have no password to check.
+        if entry.secret.trim().is_empty() && entry.otp.is_some() {
+            continue;
+        }
+        if entry.secret.trim().is_empty() {
+            findings.push(Finding::Empty {
+                entry: entry.name.clone(),
+            });
+            continue;
+        }
+        let strength = entry.strength();
+        if strength.score < options.min_score {
+            findings.push(Finding::Weak {
+                entry: entry.name.clone(),
+                score: strength.score,
+                warning: strength.warning,
+            });
+        }
+    }
+
+    for entry in &entries {
+        let age_days = options.now.saturating_sub(entry.modified) / SECONDS_PER_DAY;
+        if age_days > options.max_age_days {
+            findings.push(Finding::Old {
+                entry: entry.name.clone(),
+                age_days,
+            });
+        }
+    }
+
+    for (i, a) in entries.iter().enumerate() {
+        for b in &entries[i + 1..] {
+            if names_look_alike(&a.name, &b.name) {
+                findings.push(Finding::NearDuplicate {
+                    entries: vec![a.name.clone(), b.name.clone()],
+                });
+            }
+        }
+    }
+
+    AuditReport {
+        entries_checked: entries.len(),
+        findings,
+    }
+}
+
+/// Names are near duplicates when they match after dropping case and
+/// punctuation, or differ by a single edit.
+fn names_look_alike(a: &str, b: &str) -> bool {
+    let normalize = |name: &str| -> Vec<char> {
+        name.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(|c| c.to_lowercase())
+            .collect()
+    };
+    let (a, b) = (normalize(a), normalize(b));
+    if a == b {
+        return true;
+    }
+    a.len().min(b.len()) >= 4 && edit_distance(&a, &b) <= 1
+}
+
+/// Levenshtein distance between two character slices.
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, secret: &str, modified: u64) -> Entry {
+        let mut entry = Entry::new(name.to_string(), secret.to_string());
+        entry.modified = modified;
+        entry
+    }
+
+    #[test]
+    fn test_audit_finds_each_kind() {
+        let now = 1_000 * SECONDS_PER_DAY;
+        let strong = "x7#Qm9!vL2@pR4zW";
+        let entries = vec![
+            entry("github", strong, now),
+            entry("Github_", "kT8$wq2!Nz5@yLp0", now),
+            entry("gitlab", strong, now),
+            entry("router", "password", now),
+            entry("legacy", "V9!rm2#Qz8$kLw4p", now - 400 * SECONDS_PER_DAY),
+            entry("blank", "  ", now),
+        ];
+        let options = AuditOptions {
+            now,
+            ..AuditOptions::default()
+        };
+
+        let report = audit(&entries, &options);
+        assert_eq!(report.entries_checked, 6);
+        assert!(report.findings.contains(&Finding::Reused {
+            entries: vec!["github".to_string(), "gitlab".to_string()]
+        }));
+        assert!(
+            report
+                .findings
+                .iter()
+                .any(|f| matches!(f, Finding::Weak { entry, .. } if entry == "router"))
+        );
+        assert!(report.findings.contains(&Finding::Old {
+            entry: "legacy".to_string(),
+            age_days: 400
+        }));
+        assert!(report.findings.contains(&Finding::Empty {
+            entry: "blank".to_string()
+        }));
+        assert!(report.findings.contains(&Finding::NearDuplicate {
+            entries: vec!["Github_".to_string(), "github".to_string()]
+        }));
+    }
+
+    #[test]
+    fn test_names_look_alike() {
+        assert!(names_look_alike("AWS-Prod", "aws_prod"));
+        assert!(names_look_alike("github", "githb"));
+        assert!(!names_look_alike("db", "dc"));
+        assert!(!names_look_alike("email", "bank"));
+    }
+}
@@ -0,0 +1,322 @@
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::entry::Entry;
+
+/// Environment variable naming the breach database used by `add` and
+/// `breach-check`.
+pub const DB_ENV: &str = "WALLET_PWNED_DB";
+
+/// Magic bytes at the start of a binary index built by [`build_index`].
+pub const INDEX_MAGIC: &[u8; 4] = b"WPWN";
+
+/// Current binary index version.
+pub const INDEX_VERSION: u8 = 1;
+
+const HASH_LEN: usize = 20;
+const HEADER_LEN: u64 = INDEX_MAGIC.len() as u64 + 1;
+/// Each index record is a SHA-1 hash followed by a big-endian `u32` count.
+const RECORD_LEN: u64 = HASH_LEN as u64 + 4;
+
+type Hash = [u8; HASH_LEN];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// The Have I Been Pwned "ordered by hash" download: `HASH:COUNT` lines.
+    Text,
+    /// Fixed-size records written by [`build_index`].
+    Index,
+}
+
+/// A local copy of the Have I Been Pwned SHA-1 password list.
+///
+/// Lookups binary search the file on disk, so neither format is loaded into
+/// memory and nothing is sent over the network.
+pub struct BreachDb {
+    reader: BufReader<File>,
+    format: Format,
+    len: u64,
+}
+
+impl BreachDb {
+    /// Opens a text dump or binary index, detecting the format from the
+    /// first bytes of the file.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut file = File::open(path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Cannot open breach database {}: {}", path.display(), e),
+            )
+        })?;
+        let len = file.metadata()?.len();
+
+        let mut magic = [0u8; 5];
+        let format = match file.read_exact(&mut magic) {
+            Ok(()) if &magic[..4] == INDEX_MAGIC => {
+                if magic[4] != INDEX_VERSION {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Unsupported breach index version {}", magic[4]),
+                    ));
+                }
+                if !(len - HEADER_LEN).is_multiple_of(RECORD_LEN) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Breach index is truncated",
+                    ));
+                }
+                Format::Index
+            }
+            _ => Format::Text,
+        };
+
+        Ok(BreachDb {
+            reader: BufReader::new(file),
+            format,
+            len,
+        })
+    }
+
+    /// Returns how often `secret` appears in known breaches, or `None` if it
+    /// does not appear at all.
+    pub fn lookup(&mut self, secret: &str) -> io::Result<Option<u64>> {
+        let hash: Hash = Sha1::digest(secret.as_bytes()).into();
+        match self.format {
+            Format::Text => self.lookup_text(&hash),
+            Format::Index => self.lookup_index(&hash),
+        }
+    }
+
+    fn lookup_index(&mut self, hash: &Hash) -> io::Result<Option<u64>> {
+        let (mut lo, mut hi) = (0, (self.len - HEADER_LEN) / RECORD_LEN);
+        let mut record = [0u8; RECORD_LEN as usize];
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            self.reader
+                .seek(SeekFrom::Start(HEADER_LEN + mid * RECORD_LEN))?;
+            self.reader.read_exact(&mut record)?;
+            match record[..HASH_LEN].cmp(hash) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => {
+                    let count = u32::from_be_bytes(record[HASH_LEN..].try_into().unwrap());
+                    return Ok(Some(count as u64));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn lookup_text(&mut self, hash: &Hash) -> io::Result<Option<u64>> {
+        // Find the smallest offset whose following line is not below `hash`.
+        let (mut lo, mut hi) = (0, self.len);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.line_after(mid)? {
+                Some(line) if parse_line(&line)?.0 < *hash => lo = mid + 1,
+                _ => hi = mid,
+            }
+        }
+        match self.line_after(lo)? {
+            Some(line) => {
+                let (found, count) = parse_line(&line)?;
+                Ok((found == *hash).then_some(count))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the first complete line starting at or after `offset`.
+    fn line_after(&mut self, offset: u64) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        if offset > 0 {
+            // Skip the rest of the line containing the previous byte.
+            self.reader.seek(SeekFrom::Start(offset - 1))?;
+            self.reader.read_line(&mut line)?;
+            line.clear();
+        } else {
+            self.reader.seek(SeekFrom::Start(0))?;
+        }
+        loop {
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            if !line.trim().is_empty() {
+                return Ok(Some(line));
+            }
+            line.clear();
+        }
+    }
+}
+
+/// Parses a `HASH:COUNT` line from the Have I Been Pwned text dump.
+fn parse_line(line: &str) -> io::Result<(Hash, u64)> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid line in breach database: {:?}", line.trim()),
+        )
+    };
+    let (hex, count) = line.trim().split_once(':').ok_or_else(invalid)?;
+    if hex.len() != HASH_LEN * 2 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let mut hash = [0u8; HASH_LEN];
+    for (i, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    let count = count.parse().map_err(|_| invalid())?;
+    Ok((hash, count))
+}
+
+/// Converts a sorted `HASH:COUNT` text dump into the compact binary index,
+/// returning the number of hashes written. Counts above `u32::MAX` are
+/// capped.
+pub fn build_index(text: impl AsRef<Path>, index: impl AsRef<Path>) -> io::Result<u64> {
+    let index = index.as_ref();
+    let tmp = index.with_extension("tmp");
+    let reader = BufReader::new(File::open(text)?);
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    writer.write_all(INDEX_MAGIC)?;
+    writer.write_all(&[INDEX_VERSION])?;
+
+    let mut previous: Option<Hash> = None;
+    let mut written = 0;
+    let result = (|| {
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (hash, count) = parse_line(&line)?;
+            if previous.is_some_and(|previous| previous >= hash) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Breach database is not sorted by hash; download the ordered-by-hash file",
+                ));
+            }
+            previous = Some(hash);
+            writer.write_all(&hash)?;
+            writer.write_all(&(count.min(u32::MAX as u64) as u32).to_be_bytes())?;
+            written += 1;
+        }
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()
+    })();
+
+    match result {
+        Ok(()) => {
+            fs::rename(&tmp, index)?;
+            Ok(written)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
+
+/// An entry whose secret appears in the breach database.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Breach {
+    pub entry: String,
+    pub count: u64,
+}
+
+/// Looks up every entry's secret, returning breached entries sorted by name.
+pub fn check<'a>(
+    db: &mut BreachDb,
+    entries: impl IntoIterator<Item = &'a Entry>,
+) -> io::Result<Vec<Breach>> {
+    let mut breaches = Vec::new();
+    // An empty secret (such as an OTP-only entry) has no password to look up.
+    for entry in entries.into_iter().filter(|entry| !entry.secret.is_empty()) {
+        if let Some(count) = db.lookup(&entry.secret)? {
+            breaches.push(Breach {
+                entry: entry.name.clone(),
+                count,
+            });
+        }
+    }
+    breaches.sort_by(|a, b| a.entry.cmp(&b.entry));
+    Ok(breaches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_dump(name: &str, secrets: &[(&str, u64)]) -> std::path::PathBuf {
+        let mut lines: Vec<String> = secrets
+            .iter()
+            .map(|(secret, count)| {
+                let hash = Sha1::digest(secret.as_bytes());
+                let hex: String = hash.iter().map(|b| format!("{:02X}", b)).collect();
+                format!("{}:{}\r\n", hex, count)
+            })
+            .collect();
+        lines.sort();
+        let path =
+            std::env::temp_dir().join(format!("wallet-breach-{}-{}", name, std::process::id()));
+        fs::write(&path, lines.concat()).unwrap();
+        path
+    }
+
+    const SECRETS: &[(&str, u64)] = &[
+        ("password", 9_545_824),
+        ("123456", 37_359_195),
+        ("hunter2", 17_043),
+        ("letmein", 539_102),
+        ("qwerty", 10_000_000_000),
+    ];
+
+    #[test]
+    fn test_lookup_text_dump() {
+        let path = write_dump("text", SECRETS);
+        let mut db = BreachDb::open(&path).unwrap();
+        for (secret, count) in SECRETS {
+            assert_eq!(db.lookup(secret).unwrap(), Some(*count), "{}", secret);
+        }
+        assert_eq!(db.lookup("x7#Qm9!vL2@pR4zW").unwrap(), None);
+        assert_eq!(db.lookup("").unwrap(), None);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_build_and_lookup_index() {
+        let text = write_dump("index-src", SECRETS);
+        let index = text.with_extension("idx");
+        assert_eq!(build_index(&text, &index).unwrap(), SECRETS.len() as u64);
+
+        let mut db = BreachDb::open(&index).unwrap();
+        assert_eq!(db.lookup("hunter2").unwrap(), Some(17_043));
+        assert_eq!(db.lookup("qwerty").unwrap(), Some(u32::MAX as u64));
+        assert_eq!(db.lookup("x7#Qm9!vL2@pR4zW").unwrap(), None);
+
+        fs::remove_file(&text).unwrap();
+        fs::remove_file(&index).unwrap();
+    }
+
+    #[test]
+    fn test_build_index_rejects_unsorted_dump() {
+        let text =
+            std::env::temp_dir().join(format!("wallet-breach-unsorted-{}", std::process::id()));
+        fs::write(
+            &text,
+            "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF:1\n0000000000000000000000000000000000000000:2\n",
+        )
+        .unwrap();
+        let index = text.with_extension("idx");
+        assert_eq!(
+            build_index(&text, &index).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert!(!index.exists());
+        fs::remove_file(&text).unwrap();
+    }
+}
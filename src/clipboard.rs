@@ -1,6 +1,8 @@
 use clipboard::{ClipboardContext, ClipboardProvider};
 use std::process::Command;
 
+use crate::secret::SecretString;
+
 /// A module for handling clipboard operations
 pub struct Clipboard;
 
@@ -103,7 +105,7 @@ impl Clipboard {
     ///
     /// # Returns
     ///
-    /// * `Result<String, ClipboardError>` - The clipboard content if successful, Err if failed
+    /// * `Result<SecretString, ClipboardError>` - The clipboard content if successful, Err if failed
     ///
     /// # Examples
     ///
@@ -111,16 +113,17 @@ impl Clipboard {
     /// use wallet::clipboard::Clipboard;
     ///
     /// match Clipboard::paste() {
-    ///     Ok(content) => println!("Clipboard contains: {}", content),
+    ///     Ok(content) => println!("Clipboard holds {} bytes", content.len()),
     ///     Err(e) => println!("Failed to read clipboard: {}", e),
     /// }
     /// ```
-    pub fn paste() -> Result<String, ClipboardError> {
+    pub fn paste() -> Result<SecretString, ClipboardError> {
         let mut ctx: ClipboardContext = ClipboardProvider::new().map_err(|e| {
             ClipboardError::PasteFailed(format!("Failed to create clipboard context: {}", e))
         })?;
 
         ctx.get_contents()
+            .map(SecretString::new)
             .map_err(|e| ClipboardError::PasteFailed(e.to_string()))
     }
 
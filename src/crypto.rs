@@ -1,74 +1,805 @@
 use aes_gcm::{
     Aes256Gcm, Nonce,
-    aead::{Aead, KeyInit, OsRng, rand_core::RngCore},
+    aead::{Aead, KeyInit, OsRng, Payload, rand_core::RngCore},
 };
+use argon2::{Algorithm, Argon2, Params, Version};
 use pbkdf2::pbkdf2_hmac;
 // use rand::RngCore;
 use sha2::Sha256;
-use std::{fs, io, path::Path};
+use std::{fmt, fs, io, path::Path};
+use zeroize::Zeroizing;
 
-const PBKDF2_ITERATIONS: u32 = 100_000;
-const SALT_LEN: usize = 16;
-const NONCE_LEN: usize = 12;
-const KEY_LEN: usize = 32;
+use crate::harden;
+use crate::header::{
+    FORMAT_VERSION, Header, Kdf, LEGACY_VERSION, MAGIC, MAX_SLOTS, NONCE_LEN, SALT_LEN, Slot,
+    SlotKind,
+};
+use crate::keyfile::Keyfile;
+use crate::recipient::{self, Identity, Recipient};
+use crate::secret::{SecretBytes, SecretString};
+use crate::storage;
 
-/// Derives a 256-bit key from a password and salt using PBKDF2 (HMAC-SHA256).
-fn derive_key(password: &str, salt: &[u8]) -> [u8; KEY_LEN] {
-    let mut key = [0u8; KEY_LEN];
-    pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, PBKDF2_ITERATIONS, &mut key);
-    key
-}
+const KEY_LEN: usize = 32;
 
-/// Encrypts the given input file using AES-256-GCM.
-/// Output file contains [salt | nonce | ciphertext].
-pub fn encrypt_file<P: AsRef<Path>>(input: P, password: &str, output: P) -> io::Result<()> {
-    let plaintext = fs::read(&input)?;
+/// A derived key, boxed so it can be locked in RAM before it is written.
+type Key = Box<Zeroizing<[u8; KEY_LEN]>>;
 
-    let mut salt = [0u8; SALT_LEN];
-    OsRng.fill_bytes(&mut salt);
+/// Associated data for data keys wrapped in a key slot.
+const WRAP_AAD: &[u8] = b"WLLT key slot";
 
-    let key = derive_key(password, &salt);
-    let cipher = Aes256Gcm::new_from_slice(&key).unwrap();
+/// Generates a random data key.
+fn random_key() -> Key {
+    let mut key = Box::new(Zeroizing::new([0u8; KEY_LEN]));
+    let _ = harden::lock(&key[..]);
+    OsRng.fill_bytes(&mut key[..]);
+    key
+}
 
+/// Encrypts `key` under `slot_key`, returning `nonce | ciphertext`.
+fn wrap_key(slot_key: &[u8; KEY_LEN], key: &[u8; KEY_LEN]) -> Vec<u8> {
     let mut nonce_bytes = [0u8; NONCE_LEN];
     OsRng.fill_bytes(&mut nonce_bytes);
-    let nonce = Nonce::from_slice(&nonce_bytes);
-
+    let cipher = Aes256Gcm::new_from_slice(slot_key).unwrap();
     let ciphertext = cipher
-        .encrypt(nonce, plaintext.as_ref())
+        .encrypt(
+            &Nonce::from(nonce_bytes),
+            Payload {
+                msg: key,
+                aad: WRAP_AAD,
+            },
+        )
         .expect("encryption failed");
 
-    let mut encrypted_data = Vec::new();
-    encrypted_data.extend_from_slice(&salt);
-    encrypted_data.extend_from_slice(&nonce_bytes);
-    encrypted_data.extend_from_slice(&ciphertext);
+    let mut wrapped = nonce_bytes.to_vec();
+    wrapped.extend_from_slice(&ciphertext);
+    wrapped
+}
 
-    fs::write(&output, encrypted_data)?;
-    Ok(())
+/// Decrypts a key wrapped by [`wrap_key`], or `None` if `slot_key` is wrong.
+fn unwrap_key(slot_key: &[u8; KEY_LEN], wrapped: &[u8]) -> Option<Key> {
+    if wrapped.len() < NONCE_LEN {
+        return None;
+    }
+    let (nonce_bytes, ciphertext) = wrapped.split_at(NONCE_LEN);
+    let nonce_bytes: [u8; NONCE_LEN] = nonce_bytes.try_into().ok()?;
+    let cipher = Aes256Gcm::new_from_slice(slot_key).unwrap();
+    let plaintext = Zeroizing::new(
+        cipher
+            .decrypt(
+                &Nonce::from(nonce_bytes),
+                Payload {
+                    msg: ciphertext,
+                    aad: WRAP_AAD,
+                },
+            )
+            .ok()?,
+    );
+    if plaintext.len() != KEY_LEN {
+        return None;
+    }
+    let mut key = Box::new(Zeroizing::new([0u8; KEY_LEN]));
+    let _ = harden::lock(&key[..]);
+    key.copy_from_slice(&plaintext);
+    Some(key)
 }
 
-/// Decrypts the given file using the provided password.
-pub fn decrypt_file<P: AsRef<Path>>(input: P, password: &str) -> io::Result<String> {
-    let data = fs::read(&input)?;
+/// Derives a 256-bit key from a password and salt using the given KDF.
+///
+/// With a keyfile, the KDF input is the password followed by the keyfile
+/// hash, so neither factor alone is enough to derive the key.
+fn derive_key(
+    password: &str,
+    keyfile: Option<&Keyfile>,
+    salt: &[u8],
+    kdf: &Kdf,
+) -> io::Result<Key> {
+    let mut input = Zeroizing::new(Vec::with_capacity(password.len() + 32));
+    input.extend_from_slice(password.as_bytes());
+    if let Some(keyfile) = keyfile {
+        input.extend_from_slice(keyfile.hash());
+    }
+
+    let mut key = Box::new(Zeroizing::new([0u8; KEY_LEN]));
+    let _ = harden::lock(&key[..]);
+    match kdf {
+        Kdf::Pbkdf2Sha256 { iterations } => {
+            pbkdf2_hmac::<Sha256>(&input, salt, *iterations, &mut key[..]);
+        }
+        Kdf::Argon2id { .. } => {
+            argon2_for(kdf)?
+                .hash_password_into(&input, salt, &mut key[..])
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+        }
+    }
+    Ok(key)
+}
 
-    if data.len() < SALT_LEN + NONCE_LEN {
+/// Builds an Argon2id instance, rejecting parameters the algorithm does not accept.
+fn argon2_for(kdf: &Kdf) -> io::Result<Argon2<'static>> {
+    let Kdf::Argon2id {
+        memory_kib,
+        iterations,
+        parallelism,
+    } = *kdf
+    else {
         return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "File too short or corrupted",
+            io::ErrorKind::InvalidInput,
+            "Not an Argon2id KDF",
         ));
+    };
+
+    let params = Params::new(memory_kib, iterations, parallelism, Some(KEY_LEN))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
+/// Checks that `kdf` can be used to derive a key.
+pub fn validate_kdf(kdf: &Kdf) -> io::Result<()> {
+    match kdf {
+        Kdf::Pbkdf2Sha256 { iterations } if *iterations == 0 => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "PBKDF2 iterations must be positive",
+        )),
+        Kdf::Pbkdf2Sha256 { .. } => Ok(()),
+        Kdf::Argon2id { .. } => argon2_for(kdf).map(|_| ()),
+    }
+}
+
+/// Encrypts `plaintext` in memory using AES-256-GCM and the default KDF.
+/// Returns [header | ciphertext], with the header authenticated as associated data.
+pub fn encrypt(plaintext: &[u8], password: &str) -> io::Result<Vec<u8>> {
+    encrypt_with(plaintext, password, &Kdf::default())
+}
+
+/// Encrypts `plaintext` in memory, deriving the key with `kdf`.
+pub fn encrypt_with(plaintext: &[u8], password: &str, kdf: &Kdf) -> io::Result<Vec<u8>> {
+    Ok(KeyHandle::create(password, None, kdf)?.encrypt(plaintext))
+}
+
+/// Decrypts a buffer produced by [`encrypt`], or a legacy headerless
+/// [salt | nonce | ciphertext] buffer.
+pub fn decrypt(data: &[u8], password: &str) -> io::Result<SecretBytes> {
+    decrypt_with_kdf(data, password).map(|(plaintext, _)| plaintext)
+}
+
+/// Like [`decrypt`], but also returns the KDF the data was encrypted with so
+/// the caller can re-encrypt with the same settings.
+pub fn decrypt_with_kdf(data: &[u8], password: &str) -> io::Result<(SecretBytes, Kdf)> {
+    KeyHandle::open(data, password, None)
+        .map(|(key, plaintext)| (plaintext, key.kdf().copied().unwrap_or_default()))
+}
+
+/// The random data key a wallet is encrypted with, together with the key
+/// slots that wrap it.
+///
+/// Opening a wallet derives one slot key from the password (and keyfile, if
+/// any) and unwraps the data key with it. Every [`KeyHandle::encrypt`] then
+/// writes the same slots with a fresh nonce, so a wallet can be loaded and
+/// saved any number of times for the cost of a single derivation, and adding
+/// or removing a slot never re-encrypts the entries under a new key.
+pub struct KeyHandle {
+    key: Key,
+    slots: Vec<Slot>,
+    /// Salt of the slot the handle was created or opened with.
+    opened: Vec<u8>,
+    /// Set when the file was in an older format and should be written back.
+    upgraded: bool,
+}
+
+impl KeyHandle {
+    /// Generates a new data key with a single slot for `password` and the
+    /// optional `keyfile`.
+    pub fn create(password: &str, keyfile: Option<&Keyfile>, kdf: &Kdf) -> io::Result<Self> {
+        let mut handle = KeyHandle {
+            key: random_key(),
+            slots: Vec::new(),
+            opened: Vec::new(),
+            upgraded: false,
+        };
+        let slot = handle.wrap(password, keyfile, kdf)?;
+        handle.opened = slot.salt.clone();
+        handle.slots.push(slot);
+        Ok(handle)
+    }
+
+    /// Finds the slot `password` and `keyfile` unlock, unwraps the data key
+    /// and decrypts the buffer, returning the key for later saves together
+    /// with the plaintext.
+    ///
+    /// v0 and v1 files, encrypted directly with the derived key, are moved
+    /// to a new data key; [`KeyHandle::needs_upgrade`] then reports that the
+    /// file should be written back.
+    ///
+    /// Fails with [`io::ErrorKind::NotFound`] if every slot requires a
+    /// keyfile and none is given, and with [`io::ErrorKind::InvalidInput`]
+    /// if no slot matches the password or keyfile.
+    pub fn open(
+        data: &[u8],
+        password: &str,
+        keyfile: Option<&Keyfile>,
+    ) -> io::Result<(Self, SecretBytes)> {
+        let (header, offset) = match Header::parse(data) {
+            // A legacy salt may start with the magic bytes by chance, followed
+            // by bytes that are no valid header at all.
+            Err(e) if data.starts_with(&MAGIC) => {
+                return Header::parse_legacy(data)
+                    .and_then(|(legacy, offset)| {
+                        Self::open_with_header(&legacy, &data[offset..], password, keyfile)
+                    })
+                    .map_err(|_| e);
+            }
+            parsed => parsed?,
+        };
+        let result = Self::open_with_header(&header, &data[offset..], password, keyfile);
+
+        // Or by something that parses as a header.
+        if result.is_err()
+            && header.version != LEGACY_VERSION
+            && let Ok((legacy, offset)) = Header::parse_legacy(data)
+            && let Ok(opened) = Self::open_with_header(&legacy, &data[offset..], password, keyfile)
+        {
+            return Ok(opened);
+        }
+        result
+    }
+
+    fn open_with_header(
+        header: &Header,
+        ciphertext: &[u8],
+        password: &str,
+        keyfile: Option<&Keyfile>,
+    ) -> io::Result<(Self, SecretBytes)> {
+        if keyfile.is_none() && header.requires_keyfile() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "This wallet requires a keyfile",
+            ));
+        }
+        if keyfile.is_some() && !header.accepts_keyfile() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "This wallet is not bound to a keyfile",
+            ));
+        }
+
+        for slot in &header.slots {
+            let Some(kdf) = slot.kdf() else { continue };
+            if slot.keyfile() != keyfile.is_some() {
+                continue;
+            }
+            let slot_key = derive_key(password, keyfile, &slot.salt, kdf)?;
+            if slot.wrapped_key.is_empty() {
+                // v0/v1: the derived key encrypts the data itself.
+                let plaintext = decrypt_with_header(header, ciphertext, &slot_key)?;
+                let key = random_key();
+                let slot = Slot {
+                    wrapped_key: wrap_key(&slot_key, &key),
+                    ..slot.clone()
+                };
+                let handle = KeyHandle {
+                    key,
+                    opened: slot.salt.clone(),
+                    slots: vec![slot],
+                    upgraded: true,
+                };
+                return Ok((handle, plaintext));
+            }
+            if let Some(key) = unwrap_key(&slot_key, &slot.wrapped_key) {
+                let plaintext = decrypt_with_header(header, ciphertext, &key)?;
+                let handle = KeyHandle {
+                    key,
+                    slots: header.slots.clone(),
+                    opened: slot.salt.clone(),
+                    upgraded: false,
+                };
+                return Ok((handle, plaintext));
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Invalid password or data",
+        ))
+    }
+
+    /// Unwraps the data key from the recipient slot `identity` belongs to
+    /// and decrypts the buffer, like [`KeyHandle::open`].
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] if the identity is not a
+    /// recipient of the wallet.
+    pub fn open_with_identity(data: &[u8], identity: &Identity) -> io::Result<(Self, SecretBytes)> {
+        let (header, offset) = Header::parse(data)?;
+        for slot in header
+            .slots
+            .iter()
+            .filter(|slot| slot.recipient().is_some())
+        {
+            let Some(unwrapped) = recipient::unwrap_key(identity, &slot.wrapped_key) else {
+                continue;
+            };
+            if unwrapped.len() != KEY_LEN {
+                continue;
+            }
+            let mut key = Box::new(Zeroizing::new([0u8; KEY_LEN]));
+            let _ = harden::lock(&key[..]);
+            key.copy_from_slice(&unwrapped);
+            let plaintext = decrypt_with_header(&header, &data[offset..], &key)?;
+            let handle = KeyHandle {
+                key,
+                slots: header.slots.clone(),
+                opened: slot.salt.clone(),
+                upgraded: false,
+            };
+            return Ok((handle, plaintext));
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Identity is not a recipient of this wallet",
+        ))
+    }
+
+    /// Whether the file was opened from an older format and should be saved
+    /// to move it to key slots.
+    pub fn needs_upgrade(&self) -> bool {
+        self.upgraded
+    }
+
+    /// The key slots written with every save.
+    pub fn slots(&self) -> &[Slot] {
+        &self.slots
+    }
+
+    /// Index of the slot the handle was created or opened with.
+    pub fn opened_slot(&self) -> Option<usize> {
+        self.slots.iter().position(|slot| slot.salt == self.opened)
+    }
+
+    /// The KDF of the slot the handle was created or opened with, or `None`
+    /// if that is a recipient slot.
+    pub fn kdf(&self) -> Option<&Kdf> {
+        self.slots[self.opened_slot().unwrap_or(0)].kdf()
+    }
+
+    /// Builds a slot wrapping the data key for `password` and the optional
+    /// `keyfile`, under a freshly generated salt.
+    pub fn wrap(&self, password: &str, keyfile: Option<&Keyfile>, kdf: &Kdf) -> io::Result<Slot> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let slot_key = derive_key(password, keyfile, &salt, kdf)?;
+        Ok(Slot::password(
+            *kdf,
+            keyfile.is_some(),
+            &salt,
+            wrap_key(&slot_key, &self.key),
+        ))
+    }
+
+    /// Builds a slot wrapping the data key for `recipient`.
+    pub fn wrap_for(&self, recipient: &Recipient) -> Slot {
+        let mut id = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut id);
+        Slot {
+            kind: SlotKind::Recipient {
+                recipient: recipient.to_string(),
+            },
+            salt: id.to_vec(),
+            wrapped_key: recipient::wrap_key(recipient, &self.key[..]),
+        }
+    }
+
+    /// Replaces the data key with a new one, so removed slots can no longer
+    /// unwrap it even from a copy of an old file.
+    ///
+    /// Recipient slots are re-wrapped with their public keys. Password and
+    /// recovery slots are re-wrapped with the password (or recovery
+    /// passphrase) and keyfile `secret` returns for the slot's index, which
+    /// are checked against the slot first. If any secret is missing or
+    /// wrong, nothing changes, so no slot is ever lost.
+    pub fn rotate(
+        &mut self,
+        mut secret: impl FnMut(usize, &Slot) -> io::Result<(SecretString, Option<Keyfile>)>,
+    ) -> io::Result<()> {
+        let key = random_key();
+        let mut slots = Vec::with_capacity(self.slots.len());
+        for (index, slot) in self.slots.iter().enumerate() {
+            let wrapped_key = match (&slot.kind, slot.kdf()) {
+                (SlotKind::Recipient { recipient }, _) => {
+                    recipient::wrap_key(&recipient::parse_recipient(recipient)?, &key[..])
+                }
+                (_, Some(kdf)) => {
+                    let (password, keyfile) = secret(index, slot)?;
+                    if slot.keyfile() && keyfile.is_none() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::NotFound,
+                            format!("Key slot {} requires a keyfile", index),
+                        ));
+                    }
+                    let keyfile = keyfile.as_ref().filter(|_| slot.keyfile());
+                    let slot_key = derive_key(&password, keyfile, &slot.salt, kdf)?;
+                    if unwrap_key(&slot_key, &slot.wrapped_key).is_none() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("Invalid password or keyfile for key slot {}", index),
+                        ));
+                    }
+                    wrap_key(&slot_key, &key)
+                }
+                (_, None) => unreachable!("only recipient slots have no KDF"),
+            };
+            slots.push(Slot {
+                wrapped_key,
+                ..slot.clone()
+            });
+        }
+        self.key = key;
+        self.slots = slots;
+        Ok(())
+    }
+
+    /// Appends `slot`, returning its index.
+    pub fn add_slot(&mut self, slot: Slot) -> io::Result<usize> {
+        if self.slots.len() >= MAX_SLOTS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("A wallet can have at most {} key slots", MAX_SLOTS),
+            ));
+        }
+        self.slots.push(slot);
+        Ok(self.slots.len() - 1)
+    }
+
+    /// Replaces the slot at `index`, returning the old one. Replacing the
+    /// opened slot makes `slot` the opened one.
+    pub fn replace_slot(&mut self, index: usize, slot: Slot) -> Slot {
+        if self.opened_slot() == Some(index) {
+            self.opened = slot.salt.clone();
+        }
+        std::mem::replace(&mut self.slots[index], slot)
+    }
+
+    /// Removes every slot but the opened one for which `keep` returns false,
+    /// returning how many were removed.
+    pub fn retain_slots(&mut self, mut keep: impl FnMut(&Slot) -> bool) -> usize {
+        let before = self.slots.len();
+        let opened = self.opened.clone();
+        self.slots.retain(|slot| slot.salt == opened || keep(slot));
+        before - self.slots.len()
     }
 
-    let salt = &data[..SALT_LEN];
-    let nonce_bytes = &data[SALT_LEN..SALT_LEN + NONCE_LEN];
-    let ciphertext = &data[SALT_LEN + NONCE_LEN..];
+    /// Removes the slot at `index`. The last slot and the slot the handle
+    /// was opened with cannot be removed, so a wallet always stays openable
+    /// with a secret the caller just proved to know.
+    pub fn remove_slot(&mut self, index: usize) -> io::Result<Slot> {
+        if index >= self.slots.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No key slot {}", index),
+            ));
+        }
+        if self.slots.len() == 1 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Cannot remove the last key slot",
+            ));
+        }
+        if self.opened_slot() == Some(index) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Key slot {} is the one the wallet was unlocked with; unlock with another slot to remove it",
+                    index
+                ),
+            ));
+        }
+        Ok(self.slots.remove(index))
+    }
+
+    /// Encrypts `plaintext` under the data key with a fresh nonce, returning
+    /// [header | ciphertext] with the current slots.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        let mut nonce_bytes = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce_bytes);
+
+        let header = Header::new(self.slots.clone(), &nonce_bytes);
+        let aad = header.encode();
+
+        let cipher = Aes256Gcm::new_from_slice(&self.key[..]).unwrap();
+        let nonce = Nonce::from(nonce_bytes);
+
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: &aad,
+                },
+            )
+            .expect("encryption failed");
+
+        let mut encrypted_data = aad;
+        encrypted_data.extend_from_slice(&ciphertext);
+        encrypted_data
+    }
+
+    /// Decrypts `data` without deriving again, taking over its slots so
+    /// slots added meanwhile are kept by the next save. Fails if the data
+    /// key was rotated or the opened slot removed since the handle was made.
+    pub fn decrypt(&mut self, data: &[u8]) -> io::Result<SecretBytes> {
+        if let Ok((header, offset)) = Header::parse(data)
+            && header.version == FORMAT_VERSION
+            && header.slots.iter().any(|slot| slot.salt == self.opened)
+            && let Ok(plaintext) = decrypt_with_header(&header, &data[offset..], &self.key)
+        {
+            self.slots = header.slots;
+            return Ok(plaintext);
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Key does not match the wallet file",
+        ))
+    }
+}
+
+impl fmt::Debug for KeyHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyHandle")
+            .field("key", &"<redacted>")
+            .field("slots", &self.slots.len())
+            .field("opened", &self.opened_slot())
+            .finish()
+    }
+}
+
+fn decrypt_with_header(
+    header: &Header,
+    ciphertext: &[u8],
+    key: &[u8; KEY_LEN],
+) -> io::Result<SecretBytes> {
+    let cipher = Aes256Gcm::new_from_slice(key).unwrap();
+    let nonce_bytes: [u8; NONCE_LEN] = header.nonce.as_slice().try_into().unwrap();
+    let nonce = Nonce::from(nonce_bytes);
+    let aad = header.associated_data();
+
+    cipher
+        .decrypt(
+            &nonce,
+            Payload {
+                msg: ciphertext,
+                aad: &aad,
+            },
+        )
+        .map(SecretBytes::new)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Invalid password or data"))
+}
+
+/// Reads the header of a wallet file without decrypting it.
+pub fn inspect_file<P: AsRef<Path>>(input: P) -> io::Result<Header> {
+    let data = fs::read(&input)?;
+    Header::parse(&data).map(|(header, _)| header)
+}
+
+/// Encrypts the given input file using AES-256-GCM.
+/// Output file contains [header | ciphertext] and is replaced atomically.
+pub fn encrypt_file<P: AsRef<Path>>(input: P, password: &str, output: P) -> io::Result<()> {
+    let plaintext = fs::read(&input)?;
+    storage::write_atomic(output, &encrypt(&plaintext, password)?)
+}
+
+/// Decrypts the given file using the provided password.
+pub fn decrypt_file<P: AsRef<Path>>(input: P, password: &str) -> io::Result<SecretString> {
+    let data = fs::read(&input)?;
+    let plaintext = decrypt(&data, password)?;
+
+    plaintext
+        .into_string()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Wallet is not valid UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a file in the pre-header `[salt | nonce | ciphertext]` layout.
+    fn encrypt_legacy(plaintext: &[u8], password: &str) -> Vec<u8> {
+        encrypt_legacy_with_salt(plaintext, password, [3u8; SALT_LEN])
+    }
 
-    let key = derive_key(password, salt);
-    let cipher = Aes256Gcm::new_from_slice(&key).unwrap();
-    let nonce = Nonce::from_slice(nonce_bytes);
+    fn encrypt_legacy_with_salt(plaintext: &[u8], password: &str, salt: [u8; SALT_LEN]) -> Vec<u8> {
+        let nonce_bytes = [5u8; NONCE_LEN];
+        let key = derive_key(password, None, &salt, &Kdf::legacy()).unwrap();
+        let cipher = Aes256Gcm::new_from_slice(&key[..]).unwrap();
+        let ciphertext = cipher
+            .encrypt(&Nonce::from(nonce_bytes), plaintext)
+            .unwrap();
 
-    let plaintext = cipher
-        .decrypt(nonce, ciphertext)
-        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Invalid password or data"))?;
+        let mut data = salt.to_vec();
+        data.extend_from_slice(&nonce_bytes);
+        data.extend_from_slice(&ciphertext);
+        data
+    }
+
+    /// Cheap Argon2id parameters so tests stay fast in debug builds.
+    const TEST_KDF: Kdf = Kdf::Argon2id {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    #[test]
+    fn test_encrypt_decrypt_round_trip() {
+        let data = encrypt_with(b"email:john@example.com\n", "hunter2", &TEST_KDF).unwrap();
+        assert!(data.starts_with(&crate::header::MAGIC));
+        let (plaintext, kdf) = decrypt_with_kdf(&data, "hunter2").unwrap();
+        assert_eq!(plaintext, b"email:john@example.com\n");
+        assert_eq!(kdf, TEST_KDF);
+        assert!(decrypt(&data, "wrong").is_err());
+    }
+
+    #[test]
+    fn test_key_handle_reuses_slots_with_fresh_nonces() {
+        let key = KeyHandle::create("hunter2", None, &TEST_KDF).unwrap();
+        let first = key.encrypt(b"one");
+        let second = key.encrypt(b"two");
+        let (first_header, _) = Header::parse(&first).unwrap();
+        let (second_header, _) = Header::parse(&second).unwrap();
+        assert_eq!(first_header.slots, second_header.slots);
+        assert_ne!(first_header.nonce, second_header.nonce);
+
+        let (mut opened, plaintext) = KeyHandle::open(&second, "hunter2", None).unwrap();
+        assert_eq!(plaintext, b"two");
+        assert!(!opened.needs_upgrade());
+        assert_eq!(opened.decrypt(&first).unwrap(), b"one");
+
+        let rekeyed = KeyHandle::create("hunter2", None, &TEST_KDF)
+            .unwrap()
+            .encrypt(b"three");
+        assert!(opened.decrypt(&rekeyed).is_err());
+    }
+
+    #[test]
+    fn test_each_slot_unlocks_the_same_data_key() {
+        let keyfile = Keyfile::from_bytes(b"team keyfile");
+        let mut key = KeyHandle::create("alice", None, &TEST_KDF).unwrap();
+        let bob = key.wrap("bob", Some(&keyfile), &TEST_KDF).unwrap();
+        assert_eq!(key.add_slot(bob).unwrap(), 1);
+        let data = key.encrypt(b"secret");
+
+        let (mut opened, plaintext) = KeyHandle::open(&data, "bob", Some(&keyfile)).unwrap();
+        assert_eq!(plaintext, b"secret");
+        assert_eq!(opened.opened_slot(), Some(1));
+        assert!(KeyHandle::open(&data, "bob", None).is_err());
+        assert!(KeyHandle::open(&data, "alice", Some(&keyfile)).is_err());
+
+        // Slot 1 cannot remove itself, but slot 0 can go.
+        assert!(opened.remove_slot(1).is_err());
+        opened.remove_slot(0).unwrap();
+        assert!(opened.remove_slot(0).is_err());
+        let data = opened.encrypt(b"secret");
+        assert!(KeyHandle::open(&data, "alice", None).is_err());
+        assert!(Header::parse(&data).unwrap().0.requires_keyfile());
+
+        // The agent's handle for the removed slot is no longer accepted.
+        let (mut alice, _) = KeyHandle::open(&key.encrypt(b""), "alice", None).unwrap();
+        assert!(alice.decrypt(&data).is_err());
+    }
+
+    #[test]
+    fn test_rotation_revokes_removed_recipients() {
+        let (alice, bob) = (Identity::generate(), Identity::generate());
+        let mut key = KeyHandle::create("pw", None, &TEST_KDF).unwrap();
+        key.add_slot(key.wrap_for(&alice.to_public())).unwrap();
+        key.add_slot(key.wrap_for(&bob.to_public())).unwrap();
+        let other = key.wrap("other", None, &TEST_KDF).unwrap();
+        key.add_slot(other).unwrap();
+        let data = key.encrypt(b"secret");
+
+        let (mut opened, plaintext) = KeyHandle::open_with_identity(&data, &bob).unwrap();
+        assert_eq!(plaintext, b"secret");
+        assert_eq!(opened.opened_slot(), Some(2));
+        assert_eq!(opened.kdf(), None);
+        assert!(KeyHandle::open_with_identity(&data, &Identity::generate()).is_err());
+
+        // Bob removes Alice; the password slots follow the new key once
+        // their passwords check out.
+        opened.remove_slot(1).unwrap();
+        let before = opened.encrypt(b"secret");
+        assert!(opened.rotate(|_, _| Ok(("wrong".into(), None))).is_err());
+        // A failed rotation keeps the old data key.
+        assert!(opened.decrypt(&before).is_ok());
+        let mut asked = Vec::new();
+        opened
+            .rotate(|index, slot| {
+                asked.push(index);
+                let password = if index == 0 { "pw" } else { "other" };
+                assert!(slot.kdf().is_some());
+                Ok((password.into(), None))
+            })
+            .unwrap();
+        assert_eq!(asked, [0, 2]);
+        let data = opened.encrypt(b"secret");
+        assert!(KeyHandle::open_with_identity(&data, &alice).is_err());
+        assert!(KeyHandle::open_with_identity(&data, &bob).is_ok());
+        assert!(KeyHandle::open(&data, "pw", None).is_ok());
+        assert!(KeyHandle::open(&data, "other", None).is_ok());
+    }
+
+    #[test]
+    fn test_keyfile_is_required_to_open() {
+        let keyfile = Keyfile::from_bytes(b"team keyfile");
+        let data = KeyHandle::create("hunter2", Some(&keyfile), &TEST_KDF)
+            .unwrap()
+            .encrypt(b"secret");
+        assert!(Header::parse(&data).unwrap().0.requires_keyfile());
+
+        let (opened, plaintext) = KeyHandle::open(&data, "hunter2", Some(&keyfile)).unwrap();
+        assert_eq!(plaintext, b"secret");
+        assert!(
+            Header::parse(&opened.encrypt(b"again"))
+                .unwrap()
+                .0
+                .requires_keyfile()
+        );
+
+        let missing = KeyHandle::open(&data, "hunter2", None).unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+        let wrong = Keyfile::from_bytes(b"other keyfile");
+        let wrong = KeyHandle::open(&data, "hunter2", Some(&wrong)).unwrap_err();
+        assert_eq!(wrong.kind(), io::ErrorKind::InvalidInput);
+        assert!(decrypt(&data, "hunter2").is_err());
+    }
+
+    #[test]
+    fn test_pbkdf2_wallets_still_decrypt() {
+        let kdf = Kdf::Pbkdf2Sha256 { iterations: 1_000 };
+        let data = encrypt_with(b"key:value\n", "hunter2", &kdf).unwrap();
+        assert_eq!(decrypt_with_kdf(&data, "hunter2").unwrap().1, kdf);
+    }
+
+    #[test]
+    fn test_decrypt_legacy_layout() {
+        let data = encrypt_legacy(b"key:value\n", "hunter2");
+        let (plaintext, kdf) = decrypt_with_kdf(&data, "hunter2").unwrap();
+        assert_eq!(plaintext, b"key:value\n");
+        assert_eq!(kdf, Kdf::legacy());
 
-    Ok(plaintext.iter().map(|&c| c as char).collect())
+        // Opening moves the file to a wrapped data key in a single slot.
+        let (key, _) = KeyHandle::open(&data, "hunter2", None).unwrap();
+        assert!(key.needs_upgrade());
+        let upgraded = key.encrypt(b"key:value\n");
+        let (header, _) = Header::parse(&upgraded).unwrap();
+        assert_eq!(header.version, FORMAT_VERSION);
+        assert_eq!(header.slots[0].salt, [3u8; SALT_LEN]);
+        assert_eq!(decrypt(&upgraded, "hunter2").unwrap(), b"key:value\n");
+    }
+
+    #[test]
+    fn test_legacy_salt_starting_with_magic() {
+        let mut salt = [3u8; SALT_LEN];
+        salt[..MAGIC.len()].copy_from_slice(&MAGIC);
+        salt[MAGIC.len()] = 0xFF;
+        let data = encrypt_legacy_with_salt(b"key:value\n", "hunter2", salt);
+        assert!(Header::parse(&data).is_err());
+
+        let (_, plaintext) = KeyHandle::open(&data, "hunter2", None).unwrap();
+        assert_eq!(plaintext, b"key:value\n");
+        assert!(KeyHandle::open(&data, "wrong", None).is_err());
+    }
+
+    #[test]
+    fn test_invalid_argon2_params_are_rejected() {
+        let kdf = Kdf::Argon2id {
+            memory_kib: 1,
+            iterations: 0,
+            parallelism: 1,
+        };
+        assert!(validate_kdf(&kdf).is_err());
+        assert!(encrypt_with(b"", "hunter2", &kdf).is_err());
+    }
+
+    #[test]
+    fn test_tampered_header_fails() {
+        let mut data = encrypt_with(b"key:value\n", "hunter2", &TEST_KDF).unwrap();
+        // Flip a bit in the flags field.
+        data[crate::header::MAGIC.len() + 2] ^= 1;
+        assert!(decrypt(&data, "hunter2").is_err());
+    }
 }
@@ -0,0 +1,265 @@
+use serde::{Deserialize, Serialize};
+
+use crate::otp::{self, OtpError, OtpSecret};
+use crate::secret::{SecretBytes, SecretString};
+use crate::strength::{self, Strength};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, io,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Version of the serialized entry document stored inside the ciphertext.
+pub const DOCUMENT_VERSION: u32 = 1;
+
+/// A single credential stored in the wallet.
+///
+/// Timestamps are seconds since the Unix epoch.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    pub secret: SecretString,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub urls: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    pub created: u64,
+    pub modified: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<u64>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, String>,
+    /// `otpauth://` URI or base32 seed for one-time codes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub otp: Option<SecretString>,
+}
+
+impl Entry {
+    pub fn new(name: String, secret: impl Into<SecretString>) -> Self {
+        let now = now();
+        Entry {
+            name,
+            secret: secret.into(),
+            username: None,
+            urls: Vec::new(),
+            notes: None,
+            tags: Vec::new(),
+            created: now,
+            modified: now,
+            last_used: None,
+            fields: BTreeMap::new(),
+            otp: None,
+        }
+    }
+
+    /// Replaces the secret and bumps the modification time.
+    pub fn set_secret(&mut self, secret: impl Into<SecretString>) {
+        self.secret = secret.into();
+        self.touch();
+    }
+
+    /// Marks the entry as modified now.
+    pub fn touch(&mut self) {
+        self.modified = now();
+    }
+
+    /// Estimates how hard the secret is to guess, treating the entry name
+    /// and username as known to an attacker.
+    pub fn strength(&self) -> Strength {
+        let mut user_inputs = vec![self.name.as_str()];
+        if let Some(username) = &self.username {
+            user_inputs.push(username);
+        }
+        strength::estimate(&self.secret, &user_inputs)
+    }
+
+    /// Computes the next one-time code from [`Entry::otp`], or from the
+    /// secret when it is an `otpauth://` URI. An advanced HOTP counter is
+    /// written back to where the URI came from.
+    pub fn next_otp(&mut self, now: u64) -> Result<otp::Code, OtpError> {
+        let field = self.otp.as_mut();
+        let stored = match &field {
+            Some(field) => &***field,
+            // A plain password may happen to be valid base32, so only a URI
+            // is accepted in place of the secret.
+            None if otp::has_scheme(self.secret.trim_start(), "otpauth://") => &self.secret,
+            None => return Err(OtpError::Missing),
+        };
+        let mut generator = OtpSecret::parse(stored)?;
+        let code = generator.next_code(now)?;
+        if let otp::OtpKind::Hotp { .. } = generator.kind {
+            match field {
+                Some(field) => field.replace(generator.to_uri()),
+                None => self.secret.replace(generator.to_uri()),
+            }
+        }
+        Ok(code)
+    }
+
+    /// Marks the entry as used now (shown, copied, ...).
+    pub fn mark_used(&mut self) {
+        self.last_used = Some(now());
+    }
+
+    /// Moves an OTP seed stored by older versions in the [`otp::OTP_FIELD`]
+    /// custom field to [`Entry::otp`].
+    fn migrate_otp_field(&mut self) {
+        if let Some(seed) = self.fields.remove(otp::OTP_FIELD) {
+            let seed = SecretString::new(seed);
+            if self.otp.is_none() {
+                self.otp = Some(seed);
+            }
+        }
+    }
+}
+
+/// Shows the metadata but neither the secret, the OTP seed nor the values
+/// of custom fields, which may be secrets too.
+impl fmt::Debug for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Entry")
+            .field("name", &self.name)
+            .field("secret", &self.secret)
+            .field("username", &self.username)
+            .field("urls", &self.urls)
+            .field("notes", &self.notes)
+            .field("tags", &self.tags)
+            .field("created", &self.created)
+            .field("modified", &self.modified)
+            .field("last_used", &self.last_used)
+            .field("fields", &self.fields.keys().collect::<Vec<_>>())
+            .field("otp", &self.otp)
+            .finish()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Document {
+    version: u32,
+    entries: Vec<Entry>,
+}
+
+/// Serializes the entries to the JSON document stored inside the ciphertext.
+pub fn serialize(entries: &HashMap<String, Entry>) -> SecretBytes {
+    let mut entries: Vec<Entry> = entries.values().cloned().collect();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let document = Document {
+        version: DOCUMENT_VERSION,
+        entries,
+    };
+    SecretBytes::new(serde_json::to_vec(&document).expect("Failed to serialize wallet"))
+}
+
+/// Parses decrypted wallet contents.
+///
+/// Returns the entries and whether they came from the legacy line-based
+/// `key:value` format, in which case the caller should save to upgrade.
+pub fn deserialize(data: &[u8]) -> io::Result<(HashMap<String, Entry>, bool)> {
+    let text = std::str::from_utf8(data)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Wallet is not valid UTF-8"))?;
+
+    if !text.trim_start().starts_with('{') {
+        return Ok((parse_legacy(text), true));
+    }
+
+    let document: Document = serde_json::from_str(text)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    if document.version > DOCUMENT_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unsupported wallet document version {}", document.version),
+        ));
+    }
+
+    let entries = document
+        .entries
+        .into_iter()
+        .map(|mut entry| {
+            entry.migrate_otp_field();
+            (entry.name.clone(), entry)
+        })
+        .collect();
+    Ok((entries, false))
+}
+
+/// Parses the old `key:value` per-line format. Everything after the first
+/// `:` is the value, so values containing colons survive the upgrade.
+fn parse_legacy(text: &str) -> HashMap<String, Entry> {
+    text.lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| {
+            (
+                key.to_string(),
+                Entry::new(key.to_string(), value.to_string()),
+            )
+        })
+        .collect()
+}
+
+/// Current time in seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_preserves_special_characters() {
+        let mut entry = Entry::new("db:prod".to_string(), "user:pass\nsecond line".to_string());
+        entry
+            .urls
+            .push("https://example.com:8443/login".to_string());
+        entry.tags.push("work".to_string());
+        entry.fields.insert("pin".to_string(), "1234".to_string());
+
+        let mut entries = HashMap::new();
+        entries.insert(entry.name.clone(), entry.clone());
+
+        let (parsed, legacy) = deserialize(&serialize(&entries)).unwrap();
+        assert!(!legacy);
+        assert_eq!(parsed.get("db:prod"), Some(&entry));
+    }
+
+    #[test]
+    fn test_otp_seed_is_migrated_and_redacted() {
+        let document = br#"{"version":1,"entries":[{"name":"github","secret":"pw",
+            "created":1,"modified":1,"fields":{"otp":"JBSWY3DPEHPK3PXP","pin":"1234"}}]}"#;
+        let (parsed, _) = deserialize(document).unwrap();
+        let entry = &parsed["github"];
+        assert_eq!(entry.otp.as_deref(), Some("JBSWY3DPEHPK3PXP"));
+        assert!(!entry.fields.contains_key(otp::OTP_FIELD));
+
+        let debug = format!("{:?}", entry);
+        for secret in ["JBSWY3DPEHPK3PXP", "1234", "\"pw\""] {
+            assert!(!debug.contains(secret), "{}", debug);
+        }
+        assert!(debug.contains("pin"));
+    }
+
+    #[test]
+    fn test_legacy_lines_are_upgraded() {
+        let (parsed, legacy) =
+            deserialize(b"email:john@example.com\ntoken:abc:def\nbroken\n").unwrap();
+        assert!(legacy);
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed["email"].secret, "john@example.com");
+        assert_eq!(parsed["token"].secret, "abc:def");
+    }
+
+    #[test]
+    fn test_empty_wallet_is_legacy_and_empty() {
+        let (parsed, legacy) = deserialize(b"").unwrap();
+        assert!(legacy);
+        assert!(parsed.is_empty());
+    }
+}
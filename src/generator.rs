@@ -0,0 +1,301 @@
+use rand::{Rng, TryRngCore, rngs::OsRng, seq::SliceRandom};
+
+use crate::secret::SecretString;
+
+pub const LOWERCASE: &str = "abcdefghijklmnopqrstuvwxyz";
+pub const UPPERCASE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+pub const DIGITS: &str = "0123456789";
+pub const SYMBOLS: &str = "!@#$%^&*()-_=+[]{};:,.<>/?~";
+/// Characters that are easily confused with one another when read aloud or
+/// copied by hand.
+pub const LOOK_ALIKES: &str = "0O1lI|5S2Z8B`'\"";
+
+const CONSONANTS: &str = "bcdfghjkmnprstvwxz";
+const VOWELS: &str = "aeiou";
+
+/// Rules for generating a character password.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordPolicy {
+    pub length: usize,
+    pub lowercase: bool,
+    pub uppercase: bool,
+    pub digits: bool,
+    /// Symbols to draw from; `None` disables symbols.
+    pub symbols: Option<String>,
+    /// Guarantee at least one character from every enabled class.
+    pub require_each_class: bool,
+    /// Drop characters from [`LOOK_ALIKES`].
+    pub exclude_look_alikes: bool,
+    /// Build the password from consonant/vowel syllables instead of
+    /// uniformly random characters.
+    pub pronounceable: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            length: 20,
+            lowercase: true,
+            uppercase: true,
+            digits: true,
+            symbols: Some(SYMBOLS.to_string()),
+            require_each_class: true,
+            exclude_look_alikes: false,
+            pronounceable: false,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// The character classes enabled by this policy, after filtering.
+    fn classes(&self) -> Vec<Vec<char>> {
+        let mut classes = Vec::new();
+        if self.lowercase {
+            classes.push(LOWERCASE);
+        }
+        if self.uppercase {
+            classes.push(UPPERCASE);
+        }
+        if self.digits {
+            classes.push(DIGITS);
+        }
+        if let Some(symbols) = &self.symbols {
+            classes.push(symbols);
+        }
+
+        classes
+            .into_iter()
+            .map(|class| self.filter(class))
+            .filter(|class| !class.is_empty())
+            .collect()
+    }
+
+    /// Unique characters of `chars` allowed by this policy.
+    fn filter(&self, chars: &str) -> Vec<char> {
+        let mut filtered = Vec::new();
+        for c in chars.chars() {
+            let look_alike = self.exclude_look_alikes && LOOK_ALIKES.contains(c);
+            if !look_alike && !filtered.contains(&c) {
+                filtered.push(c);
+            }
+        }
+        filtered
+    }
+}
+
+/// Error types for password generation
+#[derive(Debug, PartialEq, Eq)]
+pub enum GeneratorError {
+    NoCharacters,
+    NoWords,
+    TooShort { length: usize, required: usize },
+}
+
+impl std::fmt::Display for GeneratorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GeneratorError::NoCharacters => {
+                write!(f, "The password policy leaves no characters to choose from")
+            }
+            GeneratorError::NoWords => write!(f, "A passphrase needs at least one word"),
+            GeneratorError::TooShort { length, required } => write!(
+                f,
+                "A length of {} cannot hold one character from each of the {} required classes",
+                length, required
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GeneratorError {}
+
+/// Generates a password following `policy` using the operating system RNG.
+pub fn generate(policy: &PasswordPolicy) -> Result<SecretString, GeneratorError> {
+    let classes = policy.classes();
+    if classes.is_empty() || policy.length == 0 {
+        return Err(GeneratorError::NoCharacters);
+    }
+    if policy.require_each_class && policy.length < classes.len() {
+        return Err(GeneratorError::TooShort {
+            length: policy.length,
+            required: classes.len(),
+        });
+    }
+
+    let mut rng = OsRng.unwrap_err();
+    let mut password = if policy.pronounceable {
+        pronounceable(policy, &classes, &mut rng)
+    } else {
+        let pool: Vec<char> = classes.concat();
+        let mut password: Vec<char> = (0..policy.length)
+            .map(|_| pool[rng.random_range(0..pool.len())])
+            .collect();
+        if policy.require_each_class {
+            // Put one character of each class at distinct random positions.
+            let mut positions: Vec<usize> = (0..policy.length).collect();
+            positions.shuffle(&mut rng);
+            for (class, &position) in classes.iter().zip(&positions) {
+                password[position] = class[rng.random_range(0..class.len())];
+            }
+        }
+        password
+    };
+
+    // Sized up front so the String never reallocates and leaves a copy behind.
+    let mut result = String::with_capacity(password.iter().map(|c| c.len_utf8()).sum());
+    result.extend(password.iter());
+    password.fill('\0');
+    Ok(SecretString::new(result))
+}
+
+/// Alternating consonant/vowel syllables, with required digits, symbols and
+/// capitals mixed in at random positions.
+fn pronounceable(policy: &PasswordPolicy, classes: &[Vec<char>], rng: &mut impl Rng) -> Vec<char> {
+    let syllables = [
+        (
+            policy.filter(CONSONANTS),
+            policy.filter(&CONSONANTS.to_uppercase()),
+        ),
+        (policy.filter(VOWELS), policy.filter(&VOWELS.to_uppercase())),
+    ];
+    let pool: Vec<char> = classes.concat();
+
+    let mut password: Vec<char> = (0..policy.length)
+        .map(|i| {
+            let (lower, upper) = &syllables[i % 2];
+            let use_upper = !policy.lowercase || (policy.uppercase && rng.random_ratio(1, 6));
+            let set = match (policy.lowercase, policy.uppercase) {
+                (false, false) => &pool,
+                _ if use_upper && !upper.is_empty() => upper,
+                _ if !lower.is_empty() => lower,
+                _ => &pool,
+            };
+            set[rng.random_range(0..set.len())]
+        })
+        .collect();
+
+    if policy.require_each_class {
+        // Only overwrite characters whose classes all occur more than once, so
+        // a class that is already satisfied never loses its last character.
+        let count = |password: &[char], class: &[char]| {
+            password.iter().filter(|c| class.contains(c)).count()
+        };
+        let mut positions: Vec<usize> = (0..policy.length).collect();
+        positions.shuffle(rng);
+        for class in classes {
+            if count(&password, class) > 0 {
+                continue;
+            }
+            let spare = positions.iter().copied().find(|&position| {
+                classes
+                    .iter()
+                    .filter(|other| other.contains(&password[position]))
+                    .all(|other| count(&password, other) > 1)
+            });
+            if let Some(position) = spare {
+                password[position] = class[rng.random_range(0..class.len())];
+            }
+        }
+    }
+    password
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_policy_has_every_class() {
+        for _ in 0..50 {
+            let password = generate(&PasswordPolicy::default()).unwrap();
+            assert_eq!(password.chars().count(), 20);
+            assert!(password.chars().any(|c| c.is_ascii_lowercase()));
+            assert!(password.chars().any(|c| c.is_ascii_uppercase()));
+            assert!(password.chars().any(|c| c.is_ascii_digit()));
+            assert!(password.chars().any(|c| SYMBOLS.contains(c)));
+        }
+    }
+
+    #[test]
+    fn test_short_pronounceable_has_every_class() {
+        let policy = PasswordPolicy {
+            length: 4,
+            pronounceable: true,
+            ..PasswordPolicy::default()
+        };
+        for _ in 0..200 {
+            let password = generate(&policy).unwrap();
+            assert!(
+                password.chars().any(|c| c.is_ascii_lowercase()),
+                "{}",
+                &*password
+            );
+            assert!(
+                password.chars().any(|c| c.is_ascii_uppercase()),
+                "{}",
+                &*password
+            );
+            assert!(
+                password.chars().any(|c| c.is_ascii_digit()),
+                "{}",
+                &*password
+            );
+            assert!(
+                password.chars().any(|c| SYMBOLS.contains(c)),
+                "{}",
+                &*password
+            );
+        }
+    }
+
+    #[test]
+    fn test_custom_symbols_and_look_alikes() {
+        let policy = PasswordPolicy {
+            length: 64,
+            symbols: Some("#|".to_string()),
+            exclude_look_alikes: true,
+            ..PasswordPolicy::default()
+        };
+        let password = generate(&policy).unwrap();
+        assert!(password.chars().all(|c| !LOOK_ALIKES.contains(c)));
+        assert!(password.contains('#'));
+    }
+
+    #[test]
+    fn test_pronounceable_alternates_letters() {
+        let policy = PasswordPolicy {
+            length: 12,
+            uppercase: false,
+            digits: false,
+            symbols: None,
+            pronounceable: true,
+            ..PasswordPolicy::default()
+        };
+        let password: Vec<char> = generate(&policy).unwrap().chars().collect();
+        for (i, c) in password.iter().enumerate() {
+            let expected = if i % 2 == 0 { CONSONANTS } else { VOWELS };
+            assert!(expected.contains(*c), "{:?}", password);
+        }
+    }
+
+    #[test]
+    fn test_invalid_policies() {
+        let empty = PasswordPolicy {
+            lowercase: false,
+            uppercase: false,
+            digits: false,
+            symbols: None,
+            ..PasswordPolicy::default()
+        };
+        assert_eq!(generate(&empty), Err(GeneratorError::NoCharacters));
+
+        let short = PasswordPolicy {
+            length: 3,
+            ..PasswordPolicy::default()
+        };
+        assert!(matches!(
+            generate(&short),
+            Err(GeneratorError::TooShort { .. })
+        ));
+    }
+}
@@ -0,0 +1,228 @@
+use std::{
+    io,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Buffers [`lock`] could not pin, usually because of the memlock limit.
+static LOCK_FAILURES: AtomicUsize = AtomicUsize::new(0);
+
+/// Applies every process-wide hardening step. Failures are not fatal; they
+/// are reported by `wallet doctor`.
+pub fn harden_process() {
+    let _ = disable_core_dumps();
+    let _ = disable_ptrace();
+}
+
+/// Sets the core file size limit to zero so a crash cannot write keys or
+/// decrypted entries to disk.
+pub fn disable_core_dumps() -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if unsafe { libc::setrlimit(libc::RLIMIT_CORE, &limit) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Marks the process non-dumpable. Besides suppressing core dumps this
+/// stops other processes of the same user from attaching with ptrace or
+/// reading `/proc/<pid>/mem`.
+pub fn disable_ptrace() -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        if unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+    #[cfg(not(target_os = "linux"))]
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Pins the pages holding `bytes` in RAM so they are never written to swap.
+///
+/// The pages stay locked until the process exits: they may be shared with
+/// other secrets, which `munlock` would expose again.
+pub fn lock(bytes: &[u8]) -> io::Result<()> {
+    if bytes.is_empty() {
+        return Ok(());
+    }
+    #[cfg(unix)]
+    let result = if unsafe { libc::mlock(bytes.as_ptr().cast(), bytes.len()) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    };
+    #[cfg(not(unix))]
+    let result = Err(io::ErrorKind::Unsupported.into());
+
+    if result.is_err() {
+        LOCK_FAILURES.fetch_add(1, Ordering::Relaxed);
+    }
+    result
+}
+
+/// Outcome of one hardening step, as shown by `wallet doctor`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+/// Checks the state of each hardening step in the running process.
+/// `probe_len` bytes are locked to test memory locking; pass the size of
+/// the wallet file to learn whether its decrypted contents would fit.
+pub fn checks(probe_len: usize) -> Vec<Check> {
+    vec![
+        check_core_dumps(),
+        check_ptrace(),
+        check_memory_lock(probe_len),
+    ]
+}
+
+fn check_core_dumps() -> Check {
+    let name = "core dumps";
+    #[cfg(unix)]
+    {
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if unsafe { libc::getrlimit(libc::RLIMIT_CORE, &mut limit) } != 0 {
+            return failed(name, io::Error::last_os_error().to_string());
+        }
+        if limit.rlim_cur == 0 {
+            passed(name, "disabled (core file size limit is 0)".to_string())
+        } else {
+            failed(
+                name,
+                "enabled; a crash could write secrets to disk".to_string(),
+            )
+        }
+    }
+    #[cfg(not(unix))]
+    failed(name, "not supported on this platform".to_string())
+}
+
+fn check_ptrace() -> Check {
+    let name = "ptrace";
+    #[cfg(target_os = "linux")]
+    {
+        match unsafe { libc::prctl(libc::PR_GET_DUMPABLE, 0, 0, 0, 0) } {
+            0 => passed(
+                name,
+                "process is not dumpable; other processes cannot attach".to_string(),
+            ),
+            -1 => failed(name, io::Error::last_os_error().to_string()),
+            _ => failed(
+                name,
+                "process is dumpable; processes of the same user can read its memory".to_string(),
+            ),
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    failed(name, "not supported on this platform".to_string())
+}
+
+fn check_memory_lock(probe_len: usize) -> Check {
+    let name = "memory locking";
+    let failures = LOCK_FAILURES.load(Ordering::Relaxed);
+    if failures > 0 {
+        return failed(
+            name,
+            format!(
+                "{} secret buffer(s) could not be locked; raise the memlock limit (ulimit -l)",
+                failures
+            ),
+        );
+    }
+    let probe = vec![0u8; probe_len.max(1)];
+    match lock(&probe) {
+        Ok(()) => passed(
+            name,
+            format!(
+                "{} bytes can be pinned in RAM{}",
+                probe.len(),
+                memlock_limit()
+            ),
+        ),
+        Err(e) => failed(
+            name,
+            format!(
+                "cannot lock {} bytes ({}){}; raise it with ulimit -l",
+                probe.len(),
+                e,
+                memlock_limit()
+            ),
+        ),
+    }
+}
+
+/// Describes the memlock limit, or nothing if it cannot be read.
+fn memlock_limit() -> String {
+    #[cfg(unix)]
+    {
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) } == 0 {
+            return match limit.rlim_cur {
+                libc::RLIM_INFINITY => " (memlock limit: unlimited)".to_string(),
+                bytes => format!(" (memlock limit: {} KiB)", bytes / 1024),
+            };
+        }
+    }
+    String::new()
+}
+
+fn passed(name: &'static str, detail: String) -> Check {
+    Check {
+        name,
+        ok: true,
+        detail,
+    }
+}
+
+fn failed(name: &'static str, detail: String) -> Check {
+    Check {
+        name,
+        ok: false,
+        detail,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn test_disable_core_dumps() {
+        disable_core_dumps().unwrap();
+        assert!(check_core_dumps().ok);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_disable_ptrace() {
+        disable_ptrace().unwrap();
+        assert!(check_ptrace().ok);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_lock() {
+        lock(&[7u8; 64]).unwrap();
+        lock(&[]).unwrap();
+        assert!(check_memory_lock(4096).ok);
+    }
+}
@@ -0,0 +1,744 @@
+use std::{fmt, io};
+
+/// Magic bytes at the start of every versioned wallet file.
+pub const MAGIC: [u8; 4] = *b"WLLT";
+/// Current container format version.
+pub const FORMAT_VERSION: u8 = 2;
+/// Format version of files encrypted directly with the password-derived key.
+pub const DIRECT_KEY_VERSION: u8 = 1;
+/// Format version reported for legacy headerless `[salt | nonce | ciphertext]` files.
+pub const LEGACY_VERSION: u8 = 0;
+
+/// PBKDF2 iteration count used by legacy (v0) wallets.
+pub const LEGACY_PBKDF2_ITERATIONS: u32 = 100_000;
+pub const SALT_LEN: usize = 16;
+pub const NONCE_LEN: usize = 12;
+
+/// Argon2id defaults for new wallets: 64 MiB of memory, 3 passes, 4 lanes.
+pub const ARGON2_MEMORY_KIB: u32 = 64 * 1024;
+pub const ARGON2_ITERATIONS: u32 = 3;
+pub const ARGON2_PARALLELISM: u32 = 4;
+
+/// Upper bounds on stored KDF parameters. Argon2id may use at most 4 GiB of
+/// memory and 255 lanes, and its total cost (memory in KiB times passes) is
+/// capped at 2^26, about 340 times the defaults. PBKDF2 is capped at 100
+/// times the legacy iteration count. A crafted header can thus make one
+/// derivation at most a few hundred times slower than usual, and unlocking
+/// tries at most [`MAX_SLOTS`] of them.
+pub const MAX_KDF_MEMORY_KIB: u32 = 4 * 1024 * 1024;
+pub const MAX_KDF_COST: u64 = 1 << 26;
+pub const MAX_KDF_PARALLELISM: u32 = 255;
+pub const MAX_PBKDF2_ITERATIONS: u32 = 100 * LEGACY_PBKDF2_ITERATIONS;
+
+/// Maximum number of key slots in one wallet file.
+pub const MAX_SLOTS: usize = 8;
+
+/// v1 header flag: the key is derived from the password and a keyfile.
+pub const FLAG_KEYFILE: u16 = 0x0001;
+/// Key slot flag: the slot key is derived from the password and a keyfile.
+const SLOT_FLAG_KEYFILE: u8 = 0x01;
+
+const SLOT_PASSWORD: u8 = 1;
+const SLOT_RECOVERY: u8 = 2;
+const SLOT_RECIPIENT: u8 = 3;
+
+const KDF_PBKDF2_SHA256: u8 = 1;
+const KDF_ARGON2ID: u8 = 2;
+const CIPHER_AES256_GCM: u8 = 1;
+
+/// Key derivation function and its parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kdf {
+    Pbkdf2Sha256 {
+        iterations: u32,
+    },
+    Argon2id {
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+}
+
+/// Authenticated cipher used for the wallet payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cipher {
+    Aes256Gcm,
+}
+
+/// What unlocks a key slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlotKind {
+    /// A password, optionally combined with a keyfile.
+    Password { kdf: Kdf, keyfile: bool },
+    /// A generated recovery key, see [`crate::recovery`].
+    Recovery { kdf: Kdf },
+    /// An age X25519 public key (`age1...`); the matching identity file
+    /// unlocks the slot, see [`crate::recipient`].
+    Recipient { recipient: String },
+}
+
+/// One way of unlocking the wallet: the data key, encrypted for the slot's
+/// secret.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Slot {
+    pub kind: SlotKind,
+    /// Random per-slot value; the KDF salt of password and recovery slots.
+    pub salt: Vec<u8>,
+    /// `nonce | AES-256-GCM(slot key, data key)`, or an age file for
+    /// recipient slots. Empty for v0 and v1 files, where the derived key
+    /// encrypts the wallet itself.
+    pub wrapped_key: Vec<u8>,
+}
+
+/// Self-describing header stored in front of the wallet ciphertext.
+///
+/// Layout (all integers little-endian):
+///
+/// ```text
+/// magic[4] | version u8 | cipher id u8 | flags u16 | slot count u8 | slots
+///          | nonce len u8 | nonce
+///
+/// slot:    kind u8 | body len u16 | slot flags u8 | kdf id u8
+///          | kdf params len u8 | kdf params | salt len u8 | salt
+///          | wrapped key len u8 | wrapped key
+///
+/// recipient slot:
+///          kind u8 | body len u16 | salt len u8 | salt
+///          | recipient len u8 | recipient | wrapped key len u16 | wrapped key
+/// ```
+///
+/// The wallet is encrypted with a random data key, and every slot holds a
+/// copy of that key wrapped with its own password or for its own recipient. Version 1 files had a
+/// single implicit slot and no wrapped key:
+///
+/// ```text
+/// magic[4] | version u8 | kdf id u8 | kdf params len u8 | kdf params
+///          | cipher id u8 | flags u16 | salt len u8 | salt | nonce len u8 | nonce
+/// ```
+///
+/// The encoded header is passed to the cipher as associated data, so any
+/// tampering with it makes decryption fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub version: u8,
+    pub cipher: Cipher,
+    pub flags: u16,
+    pub slots: Vec<Slot>,
+    pub nonce: Vec<u8>,
+}
+
+impl Kdf {
+    fn id(&self) -> u8 {
+        match self {
+            Kdf::Pbkdf2Sha256 { .. } => KDF_PBKDF2_SHA256,
+            Kdf::Argon2id { .. } => KDF_ARGON2ID,
+        }
+    }
+
+    fn encode_params(&self) -> Vec<u8> {
+        match self {
+            Kdf::Pbkdf2Sha256 { iterations } => iterations.to_le_bytes().to_vec(),
+            Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => [memory_kib, iterations, parallelism]
+                .iter()
+                .flat_map(|v| v.to_le_bytes())
+                .collect(),
+        }
+    }
+
+    fn decode(id: u8, params: &[u8]) -> io::Result<Self> {
+        match id {
+            KDF_PBKDF2_SHA256 => {
+                let iterations = params
+                    .try_into()
+                    .map(u32::from_le_bytes)
+                    .map_err(|_| invalid("Invalid PBKDF2 parameters"))?;
+                Kdf::Pbkdf2Sha256 { iterations }.checked()
+            }
+            KDF_ARGON2ID => {
+                if params.len() != 12 {
+                    return Err(invalid("Invalid Argon2id parameters"));
+                }
+                let word = |i: usize| u32::from_le_bytes(params[i..i + 4].try_into().unwrap());
+                Kdf::Argon2id {
+                    memory_kib: word(0),
+                    iterations: word(4),
+                    parallelism: word(8),
+                }
+                .checked()
+            }
+            _ => Err(invalid(&format!("Unknown KDF id {}", id))),
+        }
+    }
+
+    /// Returns the settings unchanged if they are within the `MAX_KDF_*` and
+    /// [`MAX_PBKDF2_ITERATIONS`] caps.
+    pub fn checked(self) -> io::Result<Self> {
+        let (memory_kib, iterations, parallelism) = match self {
+            Kdf::Pbkdf2Sha256 { iterations } if iterations > MAX_PBKDF2_ITERATIONS => {
+                return Err(invalid(&format!(
+                    "PBKDF2 iteration count {} exceeds the limit of {}",
+                    iterations, MAX_PBKDF2_ITERATIONS
+                )));
+            }
+            Kdf::Pbkdf2Sha256 { .. } => return Ok(self),
+            Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => (memory_kib, iterations, parallelism),
+        };
+        if memory_kib > MAX_KDF_MEMORY_KIB {
+            Err(invalid(&format!(
+                "KDF memory of {} KiB exceeds the limit of {} KiB",
+                memory_kib, MAX_KDF_MEMORY_KIB
+            )))
+        } else if u64::from(memory_kib) * u64::from(iterations) > MAX_KDF_COST {
+            Err(invalid(&format!(
+                "KDF cost of {} KiB x {} passes exceeds the limit of {}",
+                memory_kib, iterations, MAX_KDF_COST
+            )))
+        } else if parallelism > MAX_KDF_PARALLELISM {
+            Err(invalid(&format!(
+                "KDF parallelism {} exceeds the limit of {}",
+                parallelism, MAX_KDF_PARALLELISM
+            )))
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+impl Kdf {
+    /// The fixed PBKDF2 setting used by legacy (v0) wallets.
+    pub fn legacy() -> Self {
+        Kdf::Pbkdf2Sha256 {
+            iterations: LEGACY_PBKDF2_ITERATIONS,
+        }
+    }
+}
+
+/// New wallets use Argon2id with the recommended parameters.
+impl Default for Kdf {
+    fn default() -> Self {
+        Kdf::Argon2id {
+            memory_kib: ARGON2_MEMORY_KIB,
+            iterations: ARGON2_ITERATIONS,
+            parallelism: ARGON2_PARALLELISM,
+        }
+    }
+}
+
+impl fmt::Display for Kdf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kdf::Pbkdf2Sha256 { iterations } => {
+                write!(f, "PBKDF2-HMAC-SHA256 ({} iterations)", iterations)
+            }
+            Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => write!(
+                f,
+                "Argon2id (memory {} KiB, {} iterations, parallelism {})",
+                memory_kib, iterations, parallelism
+            ),
+        }
+    }
+}
+
+impl Cipher {
+    fn id(&self) -> u8 {
+        match self {
+            Cipher::Aes256Gcm => CIPHER_AES256_GCM,
+        }
+    }
+
+    fn decode(id: u8) -> io::Result<Self> {
+        match id {
+            CIPHER_AES256_GCM => Ok(Cipher::Aes256Gcm),
+            _ => Err(invalid(&format!("Unknown cipher id {}", id))),
+        }
+    }
+}
+
+impl fmt::Display for Cipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cipher::Aes256Gcm => write!(f, "AES-256-GCM"),
+        }
+    }
+}
+
+impl Slot {
+    /// A password slot holding `wrapped_key`.
+    pub fn password(kdf: Kdf, keyfile: bool, salt: &[u8], wrapped_key: Vec<u8>) -> Self {
+        Slot {
+            kind: SlotKind::Password { kdf, keyfile },
+            salt: salt.to_vec(),
+            wrapped_key,
+        }
+    }
+
+    /// The KDF of password and recovery slots.
+    pub fn kdf(&self) -> Option<&Kdf> {
+        match &self.kind {
+            SlotKind::Password { kdf, .. } | SlotKind::Recovery { kdf } => Some(kdf),
+            SlotKind::Recipient { .. } => None,
+        }
+    }
+
+    /// Whether the slot is opened with a password and a keyfile.
+    pub fn keyfile(&self) -> bool {
+        matches!(self.kind, SlotKind::Password { keyfile: true, .. })
+    }
+
+    /// The public key of a recipient slot.
+    pub fn recipient(&self) -> Option<&str> {
+        match &self.kind {
+            SlotKind::Recipient { recipient } => Some(recipient),
+            _ => None,
+        }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        let mut body = Vec::new();
+        let kind = match &self.kind {
+            SlotKind::Recipient { recipient } => {
+                body.push(self.salt.len() as u8);
+                body.extend_from_slice(&self.salt);
+                body.push(recipient.len() as u8);
+                body.extend_from_slice(recipient.as_bytes());
+                body.extend_from_slice(&(self.wrapped_key.len() as u16).to_le_bytes());
+                body.extend_from_slice(&self.wrapped_key);
+                SLOT_RECIPIENT
+            }
+            SlotKind::Password { kdf, keyfile } => {
+                self.encode_derived(kdf, *keyfile, &mut body);
+                SLOT_PASSWORD
+            }
+            SlotKind::Recovery { kdf } => {
+                self.encode_derived(kdf, false, &mut body);
+                SLOT_RECOVERY
+            }
+        };
+        out.push(kind);
+        out.extend_from_slice(&(body.len() as u16).to_le_bytes());
+        out.extend_from_slice(&body);
+    }
+
+    /// Body of a slot whose key is derived from a secret with `kdf`.
+    fn encode_derived(&self, kdf: &Kdf, keyfile: bool, body: &mut Vec<u8>) {
+        let params = kdf.encode_params();
+        body.push(if keyfile { SLOT_FLAG_KEYFILE } else { 0 });
+        body.push(kdf.id());
+        body.push(params.len() as u8);
+        body.extend_from_slice(&params);
+        body.push(self.salt.len() as u8);
+        body.extend_from_slice(&self.salt);
+        body.push(self.wrapped_key.len() as u8);
+        body.extend_from_slice(&self.wrapped_key);
+    }
+
+    fn parse(reader: &mut Reader) -> io::Result<Self> {
+        let kind = reader.u8()?;
+        let body_len = reader.u16()? as usize;
+        let mut body = Reader {
+            data: reader.take(body_len)?,
+            pos: 0,
+        };
+        let (kind, salt, wrapped_key) = match kind {
+            SLOT_PASSWORD | SLOT_RECOVERY => {
+                let flags = body.u8()?;
+                let kdf_id = body.u8()?;
+                let params_len = body.u8()? as usize;
+                let kdf = Kdf::decode(kdf_id, body.take(params_len)?)?;
+                let salt_len = body.u8()? as usize;
+                let salt = body.take(salt_len)?;
+                let wrapped_len = body.u8()? as usize;
+                let kind = if kind == SLOT_PASSWORD {
+                    SlotKind::Password {
+                        kdf,
+                        keyfile: flags & SLOT_FLAG_KEYFILE != 0,
+                    }
+                } else {
+                    SlotKind::Recovery { kdf }
+                };
+                (kind, salt, body.take(wrapped_len)?)
+            }
+            SLOT_RECIPIENT => {
+                let salt_len = body.u8()? as usize;
+                let salt = body.take(salt_len)?;
+                let recipient_len = body.u8()? as usize;
+                let recipient = String::from_utf8(body.take(recipient_len)?.to_vec())
+                    .map_err(|_| invalid("Invalid recipient in key slot"))?;
+                let wrapped_len = body.u16()? as usize;
+                (
+                    SlotKind::Recipient { recipient },
+                    salt,
+                    body.take(wrapped_len)?,
+                )
+            }
+            other => return Err(invalid(&format!("Unknown key slot type {}", other))),
+        };
+        if wrapped_key.is_empty() {
+            return Err(invalid("Key slot has no wrapped key"));
+        }
+        Ok(Slot {
+            kind,
+            salt: salt.to_vec(),
+            wrapped_key: wrapped_key.to_vec(),
+        })
+    }
+}
+
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            SlotKind::Password { kdf, keyfile: true } => write!(f, "password + keyfile, {}", kdf),
+            SlotKind::Password { kdf, .. } => write!(f, "password, {}", kdf),
+            SlotKind::Recovery { kdf } => write!(f, "recovery key, {}", kdf),
+            SlotKind::Recipient { recipient } => write!(f, "recipient {}", recipient),
+        }
+    }
+}
+
+impl Header {
+    /// Creates a current-version header with the given key slots and nonce.
+    pub fn new(slots: Vec<Slot>, nonce: &[u8]) -> Self {
+        Header {
+            version: FORMAT_VERSION,
+            cipher: Cipher::Aes256Gcm,
+            flags: 0,
+            slots,
+            nonce: nonce.to_vec(),
+        }
+    }
+
+    /// Serializes the header. The result is also the AEAD associated data.
+    pub fn encode(&self) -> Vec<u8> {
+        if self.version == DIRECT_KEY_VERSION {
+            return self.encode_direct();
+        }
+
+        let mut out = Vec::new();
+        out.extend_from_slice(&MAGIC);
+        out.push(self.version);
+        out.push(self.cipher.id());
+        out.extend_from_slice(&self.flags.to_le_bytes());
+        out.push(self.slots.len() as u8);
+        for slot in &self.slots {
+            slot.encode(&mut out);
+        }
+        out.push(self.nonce.len() as u8);
+        out.extend_from_slice(&self.nonce);
+        out
+    }
+
+    /// Serializes a v1 header, which describes its only slot inline.
+    fn encode_direct(&self) -> Vec<u8> {
+        let slot = &self.slots[0];
+        let kdf = slot.kdf().expect("v1 headers hold a password slot");
+        let params = kdf.encode_params();
+
+        let mut out = Vec::new();
+        out.extend_from_slice(&MAGIC);
+        out.push(self.version);
+        out.push(kdf.id());
+        out.push(params.len() as u8);
+        out.extend_from_slice(&params);
+        out.push(self.cipher.id());
+        out.extend_from_slice(&self.flags.to_le_bytes());
+        out.push(slot.salt.len() as u8);
+        out.extend_from_slice(&slot.salt);
+        out.push(self.nonce.len() as u8);
+        out.extend_from_slice(&self.nonce);
+        out
+    }
+
+    /// Parses the header at the start of `data`.
+    ///
+    /// Returns the header and the number of bytes it occupies. Files without
+    /// the magic bytes are reported as the legacy v0 layout.
+    pub fn parse(data: &[u8]) -> io::Result<(Header, usize)> {
+        if !data.starts_with(&MAGIC) {
+            return Self::parse_legacy(data);
+        }
+
+        let mut reader = Reader {
+            data,
+            pos: MAGIC.len(),
+        };
+        let version = reader.u8()?;
+        let (cipher, flags, slots) = match version {
+            FORMAT_VERSION => {
+                let cipher = Cipher::decode(reader.u8()?)?;
+                let flags = reader.u16()?;
+                let count = reader.u8()? as usize;
+                if count == 0 || count > MAX_SLOTS {
+                    return Err(invalid("Invalid number of key slots"));
+                }
+                let slots = (0..count)
+                    .map(|_| Slot::parse(&mut reader))
+                    .collect::<io::Result<_>>()?;
+                (cipher, flags, slots)
+            }
+            DIRECT_KEY_VERSION => {
+                let kdf_id = reader.u8()?;
+                let params_len = reader.u8()? as usize;
+                let kdf = Kdf::decode(kdf_id, reader.take(params_len)?)?;
+                let cipher = Cipher::decode(reader.u8()?)?;
+                let flags = reader.u16()?;
+                let salt_len = reader.u8()? as usize;
+                let salt = reader.take(salt_len)?;
+                let slot = Slot::password(kdf, flags & FLAG_KEYFILE != 0, salt, Vec::new());
+                (cipher, flags, vec![slot])
+            }
+            _ => {
+                return Err(invalid(&format!(
+                    "Unsupported wallet format version {}",
+                    version
+                )));
+            }
+        };
+        let nonce_len = reader.u8()? as usize;
+        if nonce_len != NONCE_LEN {
+            return Err(invalid("Invalid nonce length"));
+        }
+        let nonce = reader.take(nonce_len)?.to_vec();
+
+        let header = Header {
+            version,
+            cipher,
+            flags,
+            slots,
+            nonce,
+        };
+        Ok((header, reader.pos))
+    }
+
+    /// Interprets `data` as a headerless `[salt | nonce | ciphertext]` file.
+    pub fn parse_legacy(data: &[u8]) -> io::Result<(Header, usize)> {
+        if data.len() < SALT_LEN + NONCE_LEN {
+            return Err(invalid("File too short or corrupted"));
+        }
+
+        let header = Header {
+            version: LEGACY_VERSION,
+            cipher: Cipher::Aes256Gcm,
+            flags: 0,
+            slots: vec![Slot::password(
+                Kdf::legacy(),
+                false,
+                &data[..SALT_LEN],
+                Vec::new(),
+            )],
+            nonce: data[SALT_LEN..SALT_LEN + NONCE_LEN].to_vec(),
+        };
+        Ok((header, SALT_LEN + NONCE_LEN))
+    }
+
+    /// Whether the wallet can only be opened together with a keyfile.
+    pub fn requires_keyfile(&self) -> bool {
+        self.slots.iter().all(Slot::keyfile)
+    }
+
+    /// Whether any slot is opened with a password and a keyfile.
+    pub fn accepts_keyfile(&self) -> bool {
+        self.slots.iter().any(Slot::keyfile)
+    }
+
+    /// Associated data bound to the ciphertext. Legacy files have none.
+    pub fn associated_data(&self) -> Vec<u8> {
+        if self.version == LEGACY_VERSION {
+            Vec::new()
+        } else {
+            self.encode()
+        }
+    }
+}
+
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.version {
+            LEGACY_VERSION => writeln!(f, "Format:   v0 (legacy, headerless)")?,
+            DIRECT_KEY_VERSION => writeln!(f, "Format:   v1 (single key)")?,
+            version => writeln!(f, "Format:   v{}", version)?,
+        }
+        writeln!(f, "Cipher:   {}", self.cipher)?;
+        write!(f, "Flags:    {:#06x}", self.flags)?;
+        if self.requires_keyfile() {
+            write!(f, " (keyfile required)")?;
+        }
+        writeln!(f)?;
+        for (index, slot) in self.slots.iter().enumerate() {
+            writeln!(f, "Slot {}:   {}", index, slot)?;
+            writeln!(f, "          salt {}", hex(&slot.salt))?;
+        }
+        write!(f, "Nonce:    {}", hex(&self.nonce))
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let end = self.pos + len;
+        if end > self.data.len() {
+            return Err(invalid("Truncated wallet header"));
+        }
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes([self.u8()?, self.u8()?]))
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slot(kdf: Kdf, keyfile: bool) -> Slot {
+        Slot::password(kdf, keyfile, &[7u8; SALT_LEN], vec![5u8; 60])
+    }
+
+    #[test]
+    fn test_header_round_trip() {
+        let recovery = Slot {
+            kind: SlotKind::Recovery {
+                kdf: Kdf::default(),
+            },
+            ..slot(Kdf::default(), false)
+        };
+        let recipient = Slot {
+            kind: SlotKind::Recipient {
+                recipient: "age1example".to_string(),
+            },
+            wrapped_key: vec![5u8; 300],
+            ..slot(Kdf::default(), false)
+        };
+        let slots = vec![
+            slot(Kdf::default(), false),
+            slot(Kdf::legacy(), true),
+            recovery,
+            recipient,
+        ];
+        let header = Header::new(slots, &[9u8; NONCE_LEN]);
+        let mut encoded = header.encode();
+        let header_len = encoded.len();
+        encoded.extend_from_slice(b"ciphertext");
+
+        let (parsed, len) = Header::parse(&encoded).unwrap();
+        assert_eq!(parsed, header);
+        assert_eq!(len, header_len);
+        assert!(parsed.accepts_keyfile());
+        assert!(!parsed.requires_keyfile());
+    }
+
+    #[test]
+    fn test_headerless_file_is_legacy() {
+        let data = [1u8; SALT_LEN + NONCE_LEN + 4];
+        let (parsed, len) = Header::parse(&data).unwrap();
+        assert_eq!(parsed.version, LEGACY_VERSION);
+        assert_eq!(parsed.slots[0].kdf(), Some(&Kdf::legacy()));
+        assert!(parsed.slots[0].wrapped_key.is_empty());
+        assert_eq!(len, SALT_LEN + NONCE_LEN);
+    }
+
+    #[test]
+    fn test_argon2_params_round_trip() {
+        let kdf = Kdf::Argon2id {
+            memory_kib: 1024,
+            iterations: 2,
+            parallelism: 1,
+        };
+        let header = Header::new(vec![slot(kdf, false)], &[9u8; NONCE_LEN]);
+        let (parsed, _) = Header::parse(&header.encode()).unwrap();
+        assert_eq!(parsed.slots[0].kdf(), Some(&kdf));
+    }
+
+    #[test]
+    fn test_oversized_kdf_params_are_rejected() {
+        let argon2 = |memory_kib, iterations, parallelism| Kdf::Argon2id {
+            memory_kib,
+            iterations,
+            parallelism,
+        };
+        let max_passes = (MAX_KDF_COST / u64::from(MAX_KDF_MEMORY_KIB)) as u32;
+        for kdf in [
+            argon2(MAX_KDF_MEMORY_KIB + 1, 1, 4),
+            argon2(64 * 1024, (MAX_KDF_COST / (64 * 1024)) as u32 + 1, 4),
+            argon2(64 * 1024, 3, MAX_KDF_PARALLELISM + 1),
+            // Each value alone is allowed, but not together.
+            argon2(MAX_KDF_MEMORY_KIB, u32::MAX, 4),
+            argon2(MAX_KDF_MEMORY_KIB, max_passes + 1, 4),
+            argon2(u32::MAX, u32::MAX, u32::MAX),
+            Kdf::Pbkdf2Sha256 {
+                iterations: MAX_PBKDF2_ITERATIONS + 1,
+            },
+        ] {
+            let header = Header::new(vec![slot(kdf, false)], &[9u8; NONCE_LEN]);
+            let err = Header::parse(&header.encode()).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+
+        for kdf in [
+            argon2(MAX_KDF_MEMORY_KIB, max_passes, MAX_KDF_PARALLELISM),
+            Kdf::Pbkdf2Sha256 {
+                iterations: MAX_PBKDF2_ITERATIONS,
+            },
+        ] {
+            let header = Header::new(vec![slot(kdf, false)], &[9u8; NONCE_LEN]);
+            assert!(Header::parse(&header.encode()).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_direct_key_header_round_trip() {
+        let mut header = Header::new(
+            vec![Slot::password(
+                Kdf::default(),
+                true,
+                &[7u8; SALT_LEN],
+                Vec::new(),
+            )],
+            &[9u8; NONCE_LEN],
+        );
+        header.version = DIRECT_KEY_VERSION;
+        header.flags = FLAG_KEYFILE;
+        let (parsed, _) = Header::parse(&header.encode()).unwrap();
+        assert_eq!(parsed, header);
+        assert!(parsed.requires_keyfile());
+        assert!(parsed.to_string().contains("keyfile required"));
+    }
+
+    #[test]
+    fn test_truncated_header_is_rejected() {
+        let header = Header::new(vec![slot(Kdf::default(), false)], &[9u8; NONCE_LEN]);
+        let encoded = header.encode();
+        assert!(Header::parse(&encoded[..encoded.len() - 3]).is_err());
+        assert!(Header::parse(&encoded[..12]).is_err());
+    }
+}
@@ -0,0 +1,368 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::entry::Entry;
+use crate::otp::{self, Algorithm, OtpError, OtpKind, OtpSecret};
+use crate::secret::{SecretBytes, SecretString};
+
+/// Parses every `otpauth://` and `otpauth-migration://` URI in `text`, one
+/// per line. Blank lines are skipped.
+pub fn parse_uris(text: &str) -> Result<Vec<OtpSecret>, OtpError> {
+    let mut secrets = Vec::new();
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        if otp::has_scheme(line, "otpauth-migration://") {
+            secrets.extend(parse_migration_uri(line)?);
+        } else if otp::has_scheme(line, "otpauth://") {
+            secrets.push(OtpSecret::parse(line)?);
+        } else {
+            return Err(OtpError::InvalidUri(format!(
+                "expected otpauth:// or otpauth-migration://, got '{}'",
+                line.chars().take(24).collect::<String>()
+            )));
+        }
+    }
+    Ok(secrets)
+}
+
+/// Decodes a Google Authenticator `otpauth-migration://offline?data=...`
+/// export into one generator per account.
+pub fn parse_migration_uri(uri: &str) -> Result<Vec<OtpSecret>, OtpError> {
+    let invalid = |reason: &str| OtpError::InvalidMigration(reason.to_string());
+    let (_, query) = uri.split_once('?').ok_or_else(|| invalid("missing data"))?;
+    let data = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("data="))
+        .ok_or_else(|| invalid("missing data"))?;
+    // An unescaped '+' in the base64 payload reads back as a space.
+    let data = otp::percent_decode(data)?.replace(' ', "+");
+    let payload = base64_decode(&data).ok_or_else(|| invalid("data is not base64"))?;
+
+    let mut secrets = Vec::new();
+    for (field, value) in ProtoReader::new(&payload) {
+        if let (1, Value::Bytes(parameters)) = (field, value?) {
+            secrets.push(parse_parameters(parameters)?);
+        }
+    }
+    Ok(secrets)
+}
+
+/// Decodes one `OtpParameters` message of the migration payload.
+fn parse_parameters(message: &[u8]) -> Result<OtpSecret, OtpError> {
+    let invalid = |reason: String| OtpError::InvalidMigration(reason);
+    let text = |bytes: &[u8]| {
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid("name is not UTF-8".to_string()))
+    };
+
+    let mut secret = Vec::new();
+    let (mut name, mut issuer) = (String::new(), String::new());
+    let (mut algorithm, mut digits, mut kind, mut counter) = (0, 0, 0, 0);
+    for (field, value) in ProtoReader::new(message) {
+        match (field, value?) {
+            (1, Value::Bytes(bytes)) => secret = bytes.to_vec(),
+            (2, Value::Bytes(bytes)) => name = text(bytes)?,
+            (3, Value::Bytes(bytes)) => issuer = text(bytes)?,
+            (4, Value::Varint(value)) => algorithm = value,
+            (5, Value::Varint(value)) => digits = value,
+            (6, Value::Varint(value)) => kind = value,
+            (7, Value::Varint(value)) => counter = value,
+            _ => {}
+        }
+    }
+
+    let algorithm = match algorithm {
+        0 | 1 => Algorithm::Sha1,
+        2 => Algorithm::Sha256,
+        3 => Algorithm::Sha512,
+        4 => return Err(OtpError::UnsupportedAlgorithm("MD5".to_string())),
+        other => return Err(invalid(format!("unknown algorithm {}", other))),
+    };
+    let digits = match digits {
+        0 | 1 => 6,
+        2 => 8,
+        other => return Err(invalid(format!("unknown digit count {}", other))),
+    };
+    let kind = match kind {
+        1 => OtpKind::Hotp { counter },
+        0 | 2 => OtpKind::Totp {
+            period: otp::DEFAULT_PERIOD,
+        },
+        other => return Err(invalid(format!("unknown OTP type {}", other))),
+    };
+    if secret.is_empty() {
+        return Err(OtpError::InvalidSecret);
+    }
+
+    // The name usually repeats the issuer as an "Issuer:account" prefix.
+    let account = match name.split_once(':') {
+        Some((prefix, account)) if issuer.is_empty() || prefix.trim() == issuer => {
+            if issuer.is_empty() {
+                issuer = prefix.trim().to_string();
+            }
+            account.trim().to_string()
+        }
+        _ => name,
+    };
+    Ok(OtpSecret {
+        secret: secret.into(),
+        algorithm,
+        digits,
+        kind,
+        issuer: Some(issuer).filter(|issuer| !issuer.is_empty()),
+        account: Some(account).filter(|account| !account.is_empty()),
+    })
+}
+
+/// Directories searched for `zbarimg`, in order. `$PATH` is not consulted:
+/// the decoded secrets pass through the program, so only a copy installed
+/// by the system administrator is trusted. All of them are readable inside
+/// the sandbox.
+const ZBARIMG_DIRS: &[&str] = &["/usr/bin", "/bin", "/usr/local/bin"];
+
+/// Reads the text encoded in the QR codes of an image with `zbarimg`.
+///
+/// There is no QR decoder among our dependencies, so this relies on the
+/// zbar tools being installed in one of [`ZBARIMG_DIRS`].
+pub fn decode_qr(path: &Path) -> io::Result<SecretString> {
+    let output = find_zbarimg()
+        .and_then(|zbarimg| {
+            Command::new(zbarimg)
+                .args(["--quiet", "--raw", "-Sdisable", "-Sqrcode.enable"])
+                .arg(path)
+                .output()
+        })
+        .map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "Cannot run zbarimg to read QR codes ({}); install zbar-tools or paste the URI instead",
+                    e
+                ),
+            )
+        })?;
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("No QR code found in {}", path.display()),
+        ));
+    }
+    String::from_utf8(output.stdout)
+        .map(SecretString::new)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "QR code is not valid UTF-8"))
+}
+
+fn find_zbarimg() -> io::Result<PathBuf> {
+    ZBARIMG_DIRS
+        .iter()
+        .map(|dir| Path::new(dir).join("zbarimg"))
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("not found in {}", ZBARIMG_DIRS.join(", ")),
+            )
+        })
+}
+
+/// Reads URIs from `source`: a URI itself, a PNG screenshot of QR codes, or
+/// a text file with one URI per line.
+pub fn read_source(source: &str) -> io::Result<SecretString> {
+    if otp::has_scheme(source, "otpauth://") || otp::has_scheme(source, "otpauth-migration://") {
+        return Ok(source.into());
+    }
+    let path = Path::new(source);
+    let bytes = fs::read(path)?;
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return decode_qr(path);
+    }
+    SecretBytes::new(bytes).into_string().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is neither a PNG image nor a text file", path.display()),
+        )
+    })
+}
+
+/// Name for the entry created from an imported generator.
+pub fn entry_name(secret: &OtpSecret) -> String {
+    match (&secret.issuer, &secret.account) {
+        (Some(issuer), Some(account)) => format!("{}:{}", issuer, account),
+        (Some(name), None) | (None, Some(name)) => name.clone(),
+        (None, None) => "otp".to_string(),
+    }
+}
+
+/// Entry for an imported generator: the URI goes in [`Entry::otp`], so the
+/// seed is never shown, copied or audited as the entry's password.
+pub fn to_entry(secret: &OtpSecret) -> Entry {
+    let mut entry = Entry::new(entry_name(secret), "");
+    entry.otp = Some(SecretString::from(secret.to_uri()));
+    entry.username = secret.account.clone();
+    entry
+}
+
+/// Decodes standard or URL-safe base64, with or without padding.
+fn base64_decode(value: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for byte in value.trim_end_matches('=').bytes() {
+        let index = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | index as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// Minimal protobuf wire-format reader yielding `(field number, value)`.
+struct ProtoReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> ProtoReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        ProtoReader { data }
+    }
+
+    fn varint(&mut self) -> Result<u64, OtpError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self.data.split_first().ok_or_else(truncated)?;
+            self.data = rest;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(truncated())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], OtpError> {
+        if len > self.data.len() {
+            return Err(truncated());
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn field(&mut self) -> Result<(u64, Value<'a>), OtpError> {
+        let key = self.varint()?;
+        let value = match key & 7 {
+            0 => Value::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                Value::Fixed
+            }
+            2 => {
+                let len = self.varint()? as usize;
+                Value::Bytes(self.take(len)?)
+            }
+            5 => {
+                self.take(4)?;
+                Value::Fixed
+            }
+            wire_type => {
+                return Err(OtpError::InvalidMigration(format!(
+                    "unsupported wire type {}",
+                    wire_type
+                )));
+            }
+        };
+        Ok((key >> 3, value))
+    }
+}
+
+impl<'a> Iterator for ProtoReader<'a> {
+    type Item = (u64, Result<Value<'a>, OtpError>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        match self.field() {
+            Ok((field, value)) => Some((field, Ok(value))),
+            Err(e) => {
+                // Stop after the first error rather than misreading the rest.
+                self.data = &[];
+                Some((0, Err(e)))
+            }
+        }
+    }
+}
+
+fn truncated() -> OtpError {
+    OtpError::InvalidMigration("payload is truncated".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIGRATION: &str = "otpauth-migration://offline?data=CjEKCkhlbGxvId6tvu8SGEV4YW1wbGU6YWxpY2VAZ29vZ2xlLmNvbRoHRXhhbXBsZTAC";
+
+    #[test]
+    fn test_parse_migration_uri() {
+        let secrets = parse_migration_uri(MIGRATION).unwrap();
+        assert_eq!(secrets.len(), 1);
+        let secret = &secrets[0];
+        assert_eq!(otp::base32_encode(&secret.secret), "JBSWY3DPEHPK3PXP");
+        assert_eq!(secret.issuer.as_deref(), Some("Example"));
+        assert_eq!(secret.account.as_deref(), Some("alice@google.com"));
+        assert_eq!(secret.kind, OtpKind::Totp { period: 30 });
+        assert_eq!(secret.digits, 6);
+        assert_eq!(entry_name(secret), "Example:alice@google.com");
+
+        let mut entry = to_entry(secret);
+        assert!(entry.secret.is_empty());
+        assert_eq!(entry.username.as_deref(), Some("alice@google.com"));
+        let stored = OtpSecret::parse(entry.otp.as_deref().unwrap()).unwrap();
+        assert_eq!(otp::base32_encode(&stored.secret), "JBSWY3DPEHPK3PXP");
+        assert!(entry.next_otp(0).is_ok());
+    }
+
+    #[test]
+    fn test_parse_uris_mixes_formats() {
+        let text = format!(
+            "{}\n\n  otpauth://hotp/Bank:me?secret=GEZDGNBV&counter=4  \n",
+            MIGRATION
+        );
+        let secrets = parse_uris(&text).unwrap();
+        assert_eq!(secrets.len(), 2);
+        assert_eq!(secrets[1].kind, OtpKind::Hotp { counter: 4 });
+
+        assert!(parse_uris("JBSWY3DPEHPK3PXP").is_err());
+        assert_eq!(
+            parse_uris("OTPAuth://totp/x?secret=GEZDGNBV")
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_parse_migration_rejects_truncated_payload() {
+        assert!(matches!(
+            parse_migration_uri("otpauth-migration://offline?data=CjEKCkhlbGxv"),
+            Err(OtpError::InvalidMigration(_))
+        ));
+    }
+}
@@ -0,0 +1,127 @@
+use rand::{TryRngCore, rngs::OsRng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    path::Path,
+};
+use zeroize::Zeroizing;
+
+use crate::secret::SecretBytes;
+
+/// Environment variable naming the keyfile, as an alternative to `--keyfile`.
+pub const KEYFILE_ENV: &str = "WALLET_KEYFILE";
+
+/// Number of random bytes written by [`generate`].
+pub const GENERATED_LEN: usize = 64;
+
+/// A second unlock factor: the SHA-256 hash of a file's contents.
+///
+/// Any file works, e.g. one generated by [`generate`] or a photo kept on a
+/// USB stick. Only the hash is kept in memory and mixed into the key
+/// derivation, so the file must stay byte-for-byte identical.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Keyfile {
+    hash: SecretBytes,
+}
+
+impl Keyfile {
+    /// Hashes the file at `path`. Empty files are refused since they would
+    /// add nothing an attacker does not already know.
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut file = File::open(path)?;
+        let mut hasher = Sha256::new();
+        let mut buffer = Zeroizing::new([0u8; 8192]);
+        let mut len = 0;
+        loop {
+            let read = file.read(&mut buffer[..])?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+            len += read;
+        }
+        if len == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Keyfile {} is empty", path.display()),
+            ));
+        }
+        Ok(Keyfile {
+            hash: SecretBytes::new(hasher.finalize().to_vec()),
+        })
+    }
+
+    /// A keyfile with the given contents.
+    pub fn from_bytes(contents: &[u8]) -> Self {
+        Keyfile {
+            hash: SecretBytes::new(Sha256::digest(contents).to_vec()),
+        }
+    }
+
+    /// The SHA-256 hash of the file contents.
+    pub fn hash(&self) -> &[u8] {
+        &self.hash
+    }
+}
+
+/// Writes a new keyfile of [`GENERATED_LEN`] random bytes that only its
+/// owner can read. An existing file is never overwritten.
+pub fn generate(path: impl AsRef<Path>) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+
+    let mut contents = Zeroizing::new([0u8; GENERATED_LEN]);
+    OsRng
+        .try_fill_bytes(&mut contents[..])
+        .map_err(io::Error::other)?;
+    file.write_all(&contents[..])?;
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_and_read() {
+        let path = std::env::temp_dir().join(format!("wallet-keyfile-test-{}", std::process::id()));
+        generate(&path).unwrap();
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len(),
+            GENERATED_LEN as u64
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let keyfile = Keyfile::read(&path).unwrap();
+        assert_eq!(keyfile, Keyfile::from_bytes(&std::fs::read(&path).unwrap()));
+        assert_eq!(keyfile.hash().len(), 32);
+        assert_eq!(
+            generate(&path).unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_empty_keyfile_is_refused() {
+        let path =
+            std::env::temp_dir().join(format!("wallet-keyfile-empty-{}", std::process::id()));
+        std::fs::write(&path, b"").unwrap();
+        assert_eq!(
+            Keyfile::read(&path).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,7 +1,27 @@
-use std::{collections::HashMap, env, io::Write};
+use std::{collections::HashMap, env, io};
 
+#[cfg(unix)]
+pub mod agent;
+pub mod audit;
+pub mod breach;
 pub mod clipboard;
 pub mod crypto;
+pub mod entry;
+pub mod generator;
+pub mod harden;
+pub mod header;
+pub mod import;
+pub mod keyfile;
+pub mod otp;
+pub mod passphrase;
+pub mod prompt;
+pub mod recipient;
+pub mod recovery;
+pub mod sandbox;
+pub mod secret;
+pub mod shamir;
+pub mod storage;
+pub mod strength;
 
 pub struct Command {
     pub args: Vec<String>,
@@ -12,85 +32,492 @@ impl Command {
         let args = env::args().collect();
         Command { args }
     }
+
+    /// Removes a global flag such as `--password-stdin` from anywhere after
+    /// the program name and before a `--` terminator, returning whether it
+    /// was present.
+    pub fn take_flag(&mut self, flag: &str) -> bool {
+        match self.global_position(flag) {
+            Some(index) => {
+                self.args.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes a global option and its value, such as `--password-fd 3`,
+    /// returning the value. Exits if the option is given without a value.
+    pub fn take_option(&mut self, name: &str) -> Option<String> {
+        let index = self.global_position(name)?;
+        if index + 1 >= self.args.len() {
+            eprintln!("Error: Option '{}' requires a value", name);
+            std::process::exit(1);
+        }
+        let value = self.args.remove(index + 1);
+        self.args.remove(index);
+        Some(value)
+    }
+
+    /// Index of a global option, looking only between the program name and
+    /// a `--` terminator.
+    fn global_position(&self, name: &str) -> Option<usize> {
+        let mut globals = self.args.iter().skip(1).take_while(|arg| *arg != "--");
+        globals.position(|arg| arg == name).map(|index| index + 1)
+    }
 }
 
+pub use audit::{AuditOptions, AuditReport};
+pub use entry::Entry;
+pub use generator::{GeneratorError, PasswordPolicy};
+pub use header::{Kdf, Slot, SlotKind};
+pub use keyfile::Keyfile;
+pub use passphrase::{Capitalization, PassphrasePolicy};
+pub use recovery::RecoveryKey;
+pub use secret::{SecretBytes, SecretString};
+pub use strength::Strength;
+
+/// Environment variable naming the wallet file, instead of `~/wallet.txt`.
+pub const WALLET_FILE_ENV: &str = "WALLET_FILE";
+
 pub struct Wallet {
-    list: HashMap<String, String>,
+    list: HashMap<String, Entry>,
     file_path: String,
+    kdf: Kdf,
+    /// Key derived when the wallet was opened, reused for saving.
+    key: Option<crypto::KeyHandle>,
+    /// Second factor mixed into the key derivation, if the wallet uses one.
+    keyfile: Option<Keyfile>,
 }
 
 impl Wallet {
     pub fn new() -> Self {
         let list = HashMap::new();
-        let homepath = std::env::var("HOME").unwrap();
+        let file_path = match std::env::var(WALLET_FILE_ENV) {
+            Ok(path) if !path.is_empty() => path,
+            _ => format!("{}/wallet.txt", std::env::var("HOME").unwrap()),
+        };
         Wallet {
             list,
-            file_path: String::from(format!("{}/wallet.txt", homepath)),
+            file_path,
+            kdf: Kdf::default(),
+            key: None,
+            keyfile: None,
         }
     }
 
+    /// Creates a wallet backed by the file at `file_path` instead of `~/wallet.txt`.
+    pub fn with_path(file_path: impl Into<String>) -> Self {
+        Wallet {
+            list: HashMap::new(),
+            file_path: file_path.into(),
+            kdf: Kdf::default(),
+            key: None,
+            keyfile: None,
+        }
+    }
+
+    /// Whether a wallet file exists at the configured path.
+    pub fn exists(&self) -> bool {
+        std::path::Path::new(&self.file_path).exists()
+    }
+
+    /// Whether the wallet file's header says every slot needs a keyfile.
+    pub fn requires_keyfile(&self) -> bool {
+        crypto::inspect_file(&self.file_path).is_ok_and(|header| header.requires_keyfile())
+    }
+
+    /// Creates a new, empty wallet file encrypted with `password`, bound to
+    /// the keyfile if one was set with [`Wallet::set_keyfile`].
+    ///
+    /// Fails if a wallet already exists; wallets are never created implicitly.
+    pub fn init(&mut self, password: &str) -> io::Result<()> {
+        if self.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("A wallet already exists at {}", self.file_path),
+            ));
+        }
+        crypto::validate_kdf(&self.kdf)?;
+
+        self.list = HashMap::new();
+        let key = crypto::KeyHandle::create(password, self.keyfile.as_ref(), &self.kdf)?;
+        storage::write_atomic(&self.file_path, &key.encrypt(&self.serialize()))?;
+        self.key = Some(key);
+        Ok(())
+    }
+
+    /// Loads and decrypts the wallet, exiting the process on failure.
     pub fn load(&mut self, password: &str) {
-        //check is file exists
-        if !std::path::Path::new(&self.file_path).exists() {
-            // If the file doesn't exist, initialize an empty wallet and return
-            self.list = HashMap::new();
-            self.save(password);
+        if let Err(e) = self.try_load(password) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    /// Loads and decrypts the wallet, returning an error instead of exiting.
+    ///
+    /// A wrong password or keyfile is reported as
+    /// [`io::ErrorKind::InvalidInput`], a missing keyfile as
+    /// [`io::ErrorKind::NotFound`].
+    pub fn try_load(&mut self, password: &str) -> io::Result<()> {
+        if !self.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "No wallet found at {}. Run 'wallet init' to create one.",
+                    self.file_path
+                ),
+            ));
         }
 
-        let mut wallet_data: Vec<u8> = Vec::new();
-        let result = crypto::decrypt_file(&self.file_path, password);
-        match result {
-            Ok(decrypted_data) => {
-                wallet_data = decrypted_data.into_bytes();
+        let data = std::fs::read(&self.file_path)?;
+        let keyfile = self.keyfile.as_ref();
+        let header = header::Header::parse(&data).ok().map(|(header, _)| header);
+        let message = match keyfile {
+            None if header.as_ref().is_some_and(|h| h.requires_keyfile()) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!(
+                        "This wallet requires a keyfile; pass --keyfile FILE or set {}",
+                        keyfile::KEYFILE_ENV
+                    ),
+                ));
             }
-            Err(_) => {
-                // If decryption fails, return with an error password and clear the wallet
-                eprintln!("Error: Incorrect password or corrupted wallet file");
-                std::process::exit(1);
+            Some(_) if header.as_ref().is_some_and(|h| !h.accepts_keyfile()) => {
+                "This wallet is not bound to a keyfile"
             }
+            Some(_) => "Incorrect password or keyfile, or corrupted wallet file",
+            None => "Incorrect password or corrupted wallet file",
+        };
+        let (key, wallet_data) = crypto::KeyHandle::open(&data, password, keyfile)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, message))?;
+        let upgrade = key.needs_upgrade();
+        if let Some(kdf) = key.kdf() {
+            self.kdf = *kdf;
+        }
+        self.key = Some(key);
+
+        // Rewrite old line-based wallets in the structured format, and
+        // single-key files with a key slot, right away.
+        if self.restore(&wallet_data)? || upgrade {
+            self.save(password);
+        }
+        Ok(())
+    }
+
+    /// Loads and decrypts the wallet with a recipient's identity instead of
+    /// a password. An identity that is not a recipient is reported as
+    /// [`io::ErrorKind::InvalidInput`].
+    pub fn try_load_with_identity(&mut self, identity: &recipient::Identity) -> io::Result<()> {
+        if !self.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "No wallet found at {}. Run 'wallet init' to create one.",
+                    self.file_path
+                ),
+            ));
+        }
+
+        let data = std::fs::read(&self.file_path)?;
+        let (key, wallet_data) = crypto::KeyHandle::open_with_identity(&data, identity)?;
+        self.key = Some(key);
+        if self.restore(&wallet_data)? {
+            let key = self.key.as_ref().unwrap();
+            storage::write_atomic(&self.file_path, &key.encrypt(&self.serialize()))?;
         }
+        Ok(())
+    }
+
+    /// Replaces the entries with those in a decrypted wallet document, as
+    /// returned by the agent. Returns whether the document used the legacy
+    /// line-based format.
+    pub fn restore(&mut self, plaintext: &[u8]) -> io::Result<bool> {
+        let (list, legacy) = entry::deserialize(plaintext).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Corrupted wallet contents: {}", e),
+            )
+        })?;
+        self.list = list;
+        Ok(legacy)
+    }
+
+    /// Serializes the entries into the plaintext wallet document.
+    pub fn serialize(&self) -> SecretBytes {
+        entry::serialize(&self.list)
+    }
 
-        let buf = String::from_utf8(wallet_data).unwrap();
-        if !buf.is_empty() {
-            for line in buf.lines() {
-                let parts: Vec<&str> = line.split(':').collect();
-                if parts.len() == 2 {
-                    self.list.insert(parts[0].to_string(), parts[1].to_string());
+    /// Serializes and encrypts the wallet in memory, then atomically replaces
+    /// the wallet file so plaintext never reaches the disk.
+    ///
+    /// The data key unwrapped when the wallet was opened is reused, so
+    /// `password` is only needed if there is none yet or the KDF was changed,
+    /// in which case the unlocking slot is re-wrapped.
+    pub fn save(&mut self, password: &str) {
+        match self.key.as_mut() {
+            Some(key) => {
+                if key.kdf().is_some_and(|kdf| *kdf != self.kdf)
+                    && let Some(index) = key.opened_slot()
+                {
+                    let mut slot = key
+                        .wrap(password, self.keyfile.as_ref(), &self.kdf)
+                        .expect("Failed to derive key");
+                    if matches!(key.slots()[index].kind, SlotKind::Recovery { .. }) {
+                        slot.kind = SlotKind::Recovery { kdf: self.kdf };
+                    }
+                    key.replace_slot(index, slot);
                 }
             }
+            None => {
+                self.key = Some(
+                    crypto::KeyHandle::create(password, self.keyfile.as_ref(), &self.kdf)
+                        .expect("Failed to derive key"),
+                );
+            }
         }
+        let key = self.key.as_ref().unwrap();
+        let encrypted = key.encrypt(&self.serialize());
+        storage::write_atomic(&self.file_path, &encrypted).expect("Failed to write wallet file");
     }
 
-    pub fn save(&mut self, password: &str) {
-        let mut file = std::fs::File::options()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(self.file_path.to_string())
-            .expect("Failed to open file");
-
-        for (key, value) in &self.list {
-            writeln!(file, "{}:{}", key, value).expect("Failed to write to file");
+    /// Re-encrypts the wallet under a new random data key, wrapped for the
+    /// same password under a new salt.
+    ///
+    /// Other slots could not unwrap the new key, so they must be removed
+    /// first with [`Wallet::remove_slot`].
+    pub fn rekey(&mut self, password: &str) -> io::Result<()> {
+        let slots = self.slots().len();
+        if slots > 1 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "The wallet has {} key slots; remove all but the one you unlock with before rekeying",
+                    slots
+                ),
+            ));
+        }
+        let key = crypto::KeyHandle::create(password, self.keyfile.as_ref(), &self.kdf)?;
+        storage::write_atomic(&self.file_path, &key.encrypt(&self.serialize()))?;
+        self.key = Some(key);
+        Ok(())
+    }
+
+    /// Re-wraps the data key of the slot the wallet was unlocked with for
+    /// `new_password` and `keyfile`, which binds that slot to a (new) keyfile
+    /// or, if `None`, unbinds it. Other slots are left as they are.
+    ///
+    /// The current file is first copied to [`Wallet::backup_path`]. The
+    /// backup is only removed once the new file has been read back and
+    /// decrypted with the new password; otherwise it is restored.
+    pub fn change_password(
+        &mut self,
+        new_password: &str,
+        keyfile: Option<Keyfile>,
+    ) -> io::Result<()> {
+        let backup_path = self.backup_path();
+        let plaintext = self.serialize();
+        let key = self.key.as_mut().ok_or_else(not_unlocked)?;
+        let index = key.opened_slot().ok_or_else(not_unlocked)?;
+        match key.slots()[index].kind {
+            SlotKind::Recovery { .. } => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "The wallet was unlocked with the recovery key; use 'wallet recover' to set a new password",
+                ));
+            }
+            SlotKind::Recipient { .. } => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "The wallet was unlocked with an identity, which has no password to change",
+                ));
+            }
+            SlotKind::Password { .. } => {}
         }
 
-        crypto::encrypt_file(&self.file_path, password, &self.file_path)
-            .expect("Failed to encrypt wallet file");
+        let old_data = std::fs::read(&self.file_path)?;
+        storage::write_atomic(&backup_path, &old_data)?;
+
+        let slot = key.wrap(new_password, keyfile.as_ref(), &self.kdf)?;
+        let old_slot = key.replace_slot(index, slot);
+
+        let verified = storage::write_atomic(&self.file_path, &key.encrypt(&plaintext)).is_ok()
+            && std::fs::read(&self.file_path)
+                .and_then(|data| crypto::KeyHandle::open(&data, new_password, keyfile.as_ref()))
+                .is_ok_and(|(_, decrypted)| decrypted == plaintext);
+        if !verified {
+            key.replace_slot(index, old_slot);
+            std::fs::rename(&backup_path, &self.file_path)?;
+            return Err(io::Error::other(
+                "New wallet file failed verification; previous file restored",
+            ));
+        }
+
+        self.keyfile = keyfile;
+        std::fs::remove_file(&backup_path)
     }
 
-    pub fn add(&mut self, key: String, value: String) {
-        self.list.insert(key, value);
+    /// Key slots of the unlocked wallet.
+    pub fn slots(&self) -> &[Slot] {
+        self.key.as_ref().map_or(&[], |key| key.slots())
+    }
+
+    /// Index of the slot the wallet was unlocked with.
+    pub fn opened_slot(&self) -> Option<usize> {
+        self.key.as_ref().and_then(|key| key.opened_slot())
+    }
+
+    /// Adds a slot that unlocks the wallet with `password` and the optional
+    /// `keyfile`, and saves. Returns the index of the new slot.
+    pub fn add_slot(
+        &mut self,
+        password: &str,
+        keyfile: Option<&Keyfile>,
+        kdf: &Kdf,
+    ) -> io::Result<usize> {
+        crypto::validate_kdf(kdf)?;
+        let plaintext = self.serialize();
+        let key = self.key.as_mut().ok_or_else(not_unlocked)?;
+        let slot = key.wrap(password, keyfile, kdf)?;
+        let index = key.add_slot(slot)?;
+        storage::write_atomic(&self.file_path, &key.encrypt(&plaintext))?;
+        Ok(index)
+    }
+
+    /// Stores `recovery` in a recovery key slot, replacing any earlier
+    /// recovery key, and saves. Returns the index of the slot.
+    pub fn set_recovery_key(&mut self, recovery: &RecoveryKey) -> io::Result<usize> {
+        let plaintext = self.serialize();
+        let key = self.key.as_mut().ok_or_else(not_unlocked)?;
+        let slot = Slot {
+            kind: SlotKind::Recovery { kdf: self.kdf },
+            ..key.wrap(&recovery.passphrase(), None, &self.kdf)?
+        };
+        let is_recovery = |slot: &Slot| matches!(slot.kind, SlotKind::Recovery { .. });
+        key.retain_slots(|slot| !is_recovery(slot));
+        let index = match key.opened_slot() {
+            Some(index) if is_recovery(&key.slots()[index]) => {
+                key.replace_slot(index, slot);
+                index
+            }
+            _ => key.add_slot(slot)?,
+        };
+        storage::write_atomic(&self.file_path, &key.encrypt(&plaintext))?;
+        Ok(index)
+    }
+
+    /// Replaces every password slot with one for `new_password`, after the
+    /// wallet was unlocked with its recovery key, and saves. Returns how
+    /// many password slots were removed.
+    pub fn reset_password(&mut self, new_password: &str) -> io::Result<usize> {
+        let plaintext = self.serialize();
+        let key = self.key.as_mut().ok_or_else(not_unlocked)?;
+        let slot = key.wrap(new_password, None, &self.kdf)?;
+        let removed = key.retain_slots(|slot| !matches!(slot.kind, SlotKind::Password { .. }));
+        key.add_slot(slot)?;
+        storage::write_atomic(&self.file_path, &key.encrypt(&plaintext))?;
+        self.keyfile = None;
+        Ok(removed)
+    }
+
+    /// Removes key slot `index` and saves. The last slot and the slot the
+    /// wallet was unlocked with cannot be removed.
+    pub fn remove_slot(&mut self, index: usize) -> io::Result<()> {
+        let plaintext = self.serialize();
+        let key = self.key.as_mut().ok_or_else(not_unlocked)?;
+        key.remove_slot(index)?;
+        storage::write_atomic(&self.file_path, &key.encrypt(&plaintext))
+    }
+
+    /// Adds a slot that unlocks the wallet with the identity of `recipient`,
+    /// and saves. Returns the index of the new slot.
+    pub fn add_recipient(&mut self, recipient: &recipient::Recipient) -> io::Result<usize> {
+        let public_key = recipient.to_string();
+        if self
+            .slots()
+            .iter()
+            .any(|slot| slot.recipient() == Some(&public_key))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} is already a recipient", public_key),
+            ));
+        }
+        let plaintext = self.serialize();
+        let key = self.key.as_mut().ok_or_else(not_unlocked)?;
+        let slot = key.wrap_for(recipient);
+        let index = key.add_slot(slot)?;
+        storage::write_atomic(&self.file_path, &key.encrypt(&plaintext))?;
+        Ok(index)
+    }
+
+    /// Removes the slot of `recipient`, then moves the wallet to a new data
+    /// key so a copy of the old file is all the removed member can open,
+    /// and saves.
+    ///
+    /// Every remaining password and recovery slot is re-wrapped for the new
+    /// key with the password (or recovery passphrase) and keyfile `secret`
+    /// returns for its index, see [`crypto::KeyHandle::rotate`]. If any of
+    /// them is missing or wrong, the wallet is left unchanged.
+    pub fn remove_recipient(
+        &mut self,
+        recipient: &str,
+        secret: impl FnMut(usize, &Slot) -> io::Result<(SecretString, Option<Keyfile>)>,
+    ) -> io::Result<()> {
+        let index = self
+            .slots()
+            .iter()
+            .position(|slot| slot.recipient() == Some(recipient))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} is not a recipient", recipient),
+                )
+            })?;
+        let plaintext = self.serialize();
+        let key = self.key.as_mut().ok_or_else(not_unlocked)?;
+        if key.opened_slot() == Some(index) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The wallet was unlocked with this recipient's identity; unlock with another slot to remove it",
+            ));
+        }
+        // Rotating first keeps the slot numbers `secret` is asked about
+        // the same as in `wallet slot list`.
+        key.rotate(secret)?;
+        key.remove_slot(index)?;
+        storage::write_atomic(&self.file_path, &key.encrypt(&plaintext))
+    }
+
+    /// Location of the encrypted backup kept while the password is changed.
+    pub fn backup_path(&self) -> String {
+        format!("{}.bak", self.file_path)
+    }
+
+    /// Sets the secret of `key`, creating the entry if it does not exist.
+    pub fn add(&mut self, key: String, value: impl Into<SecretString>) {
+        match self.list.get_mut(&key) {
+            Some(entry) => entry.set_secret(value),
+            None => {
+                self.list.insert(key.clone(), Entry::new(key, value));
+            }
+        }
     }
     pub fn del(&mut self, key: &String) {
         println!("Deleting key: {}", &key);
         self.list.remove(key).expect("failed to  delete value");
     }
-    pub fn get(&self, key: &String) -> Option<&String> {
-        self.list.get(key)
+    pub fn get(&self, key: &String) -> Option<&str> {
+        self.list.get(key).map(|entry| &*entry.secret)
     }
     pub fn copy(&mut self, key: &String) -> Result<(), ()> {
-        match self.list.get(key) {
-            Some(value) => match crate::clipboard::Clipboard::copy_with_message(value) {
-                Ok(_) => Ok(()),
+        match self.list.get_mut(key) {
+            Some(entry) => match crate::clipboard::Clipboard::copy_with_message(&entry.secret) {
+                Ok(_) => {
+                    entry.mark_used();
+                    Ok(())
+                }
                 Err(_) => {
                     eprintln!("Failed to copy to clipboard");
                     Err(())
@@ -100,6 +527,45 @@ impl Wallet {
         }
     }
 
+    /// Generates a password with `policy` and stores it as the secret of
+    /// `key`, creating the entry if needed. Returns the new secret so it can
+    /// be copied without being printed.
+    pub fn generate(&mut self, key: &str, policy: &PasswordPolicy) -> Result<&str, GeneratorError> {
+        let secret = generator::generate(policy)?;
+        self.add(key.to_string(), secret);
+        Ok(&self.list[key].secret)
+    }
+
+    /// Reports reused, weak, old and empty secrets and near-duplicate names.
+    pub fn audit(&self, options: &AuditOptions) -> AuditReport {
+        audit::audit(self.list.values(), options)
+    }
+
+    /// Looks up every secret in a local breach database.
+    pub fn breach_check(&self, db: &mut breach::BreachDb) -> io::Result<Vec<breach::Breach>> {
+        breach::check(db, self.list.values())
+    }
+
+    /// Returns the full entry stored under `key`.
+    pub fn entry(&self, key: &str) -> Option<&Entry> {
+        self.list.get(key)
+    }
+
+    /// Returns the full entry stored under `key` for editing.
+    pub fn entry_mut(&mut self, key: &str) -> Option<&mut Entry> {
+        self.list.get_mut(key)
+    }
+
+    /// Inserts or replaces a complete entry, keyed by its name.
+    pub fn insert_entry(&mut self, entry: Entry) {
+        self.list.insert(entry.name.clone(), entry);
+    }
+
+    /// Iterates over all entries in no particular order.
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.list.values()
+    }
+
     pub fn clear(&mut self) {
         self.list.clear();
     }
@@ -117,6 +583,35 @@ impl Wallet {
     pub fn key_exists(&self, key: &str) -> bool {
         self.list.contains_key(key)
     }
+
+    pub fn file_path(&self) -> &str {
+        &self.file_path
+    }
+
+    /// Key derivation settings used for the wallet file.
+    pub fn kdf(&self) -> &Kdf {
+        &self.kdf
+    }
+
+    /// The keyfile used to open and save the wallet.
+    pub fn keyfile(&self) -> Option<&Keyfile> {
+        self.keyfile.as_ref()
+    }
+
+    /// Sets the keyfile used by [`Wallet::init`] and to open the wallet.
+    /// Use [`Wallet::change_password`] to bind an existing wallet to one.
+    pub fn set_keyfile(&mut self, keyfile: Option<Keyfile>) {
+        self.keyfile = keyfile;
+    }
+
+    /// Changes the key derivation settings; takes effect on the next save.
+    pub fn set_kdf(&mut self, kdf: Kdf) {
+        self.kdf = kdf;
+    }
+}
+
+fn not_unlocked() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "The wallet is not unlocked")
 }
 
 pub struct Usage {
@@ -145,13 +640,67 @@ impl Usage {
 
     pub fn default() -> Self {
         let mut usage = Self::new();
+        usage.add_command(CommandHelp {
+            flag: "init".to_string(),
+            usage: "[--force] [--pbkdf2 ITER] [--memory KIB] [--iterations N] [--parallelism P]"
+                .to_string(),
+            description: "Create a new, empty wallet; weak master passwords need --force; with --keyfile it can only be opened together with that file"
+                .to_string(),
+            examples: vec![
+                "wallet init".to_string(),
+                "wallet init --memory 262144".to_string(),
+                "wallet --keyfile /media/usb/team.key init".to_string(),
+            ],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "keyfile new".to_string(),
+            usage: "<FILE>".to_string(),
+            description: format!(
+                "Write {} random bytes to a new keyfile only you can read",
+                keyfile::GENERATED_LEN
+            ),
+            examples: vec!["wallet keyfile new /media/usb/team.key".to_string()],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "identity new".to_string(),
+            usage: "<FILE>".to_string(),
+            description: "Write a new age X25519 identity only you can read and print its public key for 'recipients add'"
+                .to_string(),
+            examples: vec!["wallet identity new ~/.config/wallet/identity.txt".to_string()],
+        });
+
         usage.add_command(CommandHelp {
             flag: "-a, --add".to_string(),
-            usage: "<KEY> <VALUE>".to_string(),
-            description: "Add or update a key-value pair in the wallet".to_string(),
+            usage: "<KEY> [VALUE | -- VALUE | --stdin | --paste [--clear-clipboard]] [--user|--url|--note|--tag|--field ...]".to_string(),
+            description: "Add or update an entry; prompts for the value (hidden) when omitted. Put -- before a value that starts with --".to_string(),
             examples: vec![
                 "wallet -a email john@example.com".to_string(),
+                "wallet -a github-token".to_string(),
+                "cat key.pem | wallet -a deploy-key --stdin".to_string(),
+                "wallet -a api-key --paste --clear-clipboard".to_string(),
                 "wallet --add github-token ghp_xxxxxxxxxxxx".to_string(),
+                "wallet -a legacy-pin -- --1234".to_string(),
+                "wallet -a github s3cret --user john --url https://github.com --tag work"
+                    .to_string(),
+            ],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "generate".to_string(),
+            usage: "[--length N] [--no-lower|--no-upper|--no-digits|--no-symbols] [--symbols SET] [--no-look-alikes] [--pronounceable] [--any-classes] [--passphrase [--words N] [--separator S] [--capitalize lower|upper|title|random] [--add-digits N] [--wordlist FILE]] [--store KEY] [--copy]".to_string(),
+            description: format!(
+                "Generate a password or passphrase ({} words from the bundled list by default); with --store or --copy it is never printed",
+                passphrase::DEFAULT_WORDS
+            ),
+            examples: vec![
+                "wallet generate --length 32".to_string(),
+                "wallet generate --store github --copy".to_string(),
+                "wallet generate --pronounceable --no-symbols".to_string(),
+                "wallet generate --passphrase --words 8 --capitalize title --add-digits 1"
+                    .to_string(),
+                "wallet generate --passphrase --wordlist eff_large_wordlist.txt".to_string(),
             ],
         });
 
@@ -192,6 +741,261 @@ impl Usage {
             ],
         });
 
+        usage.add_command(CommandHelp {
+            flag: "inspect".to_string(),
+            usage: "[FILE]".to_string(),
+            description: "Print the header of a wallet file (no password needed)".to_string(),
+            examples: vec![
+                "wallet inspect".to_string(),
+                "wallet inspect ~/backup/wallet.txt".to_string(),
+            ],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "doctor".to_string(),
+            usage: String::new(),
+            description:
+                "Check that core dumps and ptrace are disabled and secrets can be locked in RAM"
+                    .to_string(),
+            examples: vec!["wallet doctor".to_string()],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "audit".to_string(),
+            usage: "[--max-age DAYS] [--min-score 0-4] [--json] [--fail-above N]".to_string(),
+            description: "Report reused, weak, old and empty secrets and near-duplicate names"
+                .to_string(),
+            examples: vec![
+                "wallet audit".to_string(),
+                "wallet audit --json --max-age 180 --fail-above 0".to_string(),
+            ],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "breach-check".to_string(),
+            usage: "[--db FILE] [--json] | --build-index DUMP INDEX".to_string(),
+            description: format!(
+                "Look up secrets in a local Have I Been Pwned SHA-1 dump or index (default ${}); \
+                 add warns about breached values when ${} is set",
+                breach::DB_ENV,
+                breach::DB_ENV
+            ),
+            examples: vec![
+                "wallet breach-check --db pwned-passwords-sha1-ordered-by-hash.txt".to_string(),
+                "wallet breach-check --build-index pwned.txt pwned.idx".to_string(),
+            ],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "otp".to_string(),
+            usage: "<KEY> [--copy]".to_string(),
+            description: "Show the TOTP/HOTP code for an entry's otpauth URI or base32 seed (set with --otp, else the value)"
+                .to_string(),
+            examples: vec![
+                "wallet otp github".to_string(),
+                "wallet otp github --copy".to_string(),
+                "wallet -a github hunter2 --otp otpauth://totp/GitHub:me?secret=JBSWY3DPEHPK3PXP"
+                    .to_string(),
+            ],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "import-otp".to_string(),
+            usage: "<URI|FILE|IMAGE.png|->...".to_string(),
+            description: "Create one entry per account from otpauth:// or Google Authenticator otpauth-migration:// URIs (QR screenshots need zbarimg in /usr/bin, /bin or /usr/local/bin)"
+                .to_string(),
+            examples: vec![
+                "wallet import-otp 'otpauth-migration://offline?data=...'".to_string(),
+                "wallet import-otp export.png".to_string(),
+            ],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "agent".to_string(),
+            usage: "[--timeout SECS] [--socket PATH] [--foreground]".to_string(),
+            description: format!(
+                "Start an agent that keeps the unlocked key in locked memory until it is idle for SECS (default {}); commands use it when ${} is set",
+                agent::DEFAULT_IDLE_TIMEOUT.as_secs(),
+                agent::SOCK_ENV
+            ),
+            examples: vec!["eval $(wallet agent --timeout 600)".to_string()],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "lock".to_string(),
+            usage: String::new(),
+            description: "Make the agent forget all keys".to_string(),
+            examples: vec!["wallet lock".to_string()],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "passwd".to_string(),
+            usage: "[--force] [--new-keyfile FILE | --no-keyfile]".to_string(),
+            description: "Change the master password (--force accepts a weak one); bind the wallet to a new keyfile or remove the current one".to_string(),
+            examples: vec![
+                "wallet passwd".to_string(),
+                "wallet --keyfile old.key passwd --new-keyfile new.key".to_string(),
+            ],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "rekey".to_string(),
+            usage: String::new(),
+            description: "Re-encrypt the wallet under a new random data key and salt with the same password (single key slot only)"
+                .to_string(),
+            examples: vec!["wallet rekey".to_string()],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "kdf upgrade".to_string(),
+            usage: "[--pbkdf2 ITER] [--memory KIB] [--iterations N] [--parallelism P]".to_string(),
+            description: "Re-encrypt the wallet with new key derivation settings (Argon2id by default; at most 4 GiB of memory, 2^26 KiB-passes and 255 lanes; PBKDF2 at most 10,000,000 iterations)"
+                    .to_string(),
+            examples: vec![
+                "wallet kdf upgrade".to_string(),
+                "wallet kdf upgrade --memory 262144 --iterations 4".to_string(),
+            ],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "slot list".to_string(),
+            usage: String::new(),
+            description: "Show the key slots that can unlock the wallet, without unlocking it"
+                .to_string(),
+            examples: vec!["wallet slot list".to_string()],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "slot add".to_string(),
+            usage: "[--force] [--new-keyfile FILE] [--pbkdf2 ITER] [--memory KIB] [--iterations N] [--parallelism P]".to_string(),
+            description: "Add another password, optionally with a keyfile, that unlocks the wallet".to_string(),
+            examples: vec![
+                "wallet slot add".to_string(),
+                "wallet slot add --new-keyfile /media/usb/bob.key".to_string(),
+            ],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "slot remove".to_string(),
+            usage: "<N>".to_string(),
+            description: "Remove key slot N after unlocking with another slot; the last slot cannot be removed".to_string(),
+            examples: vec!["wallet slot remove 1".to_string()],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "recovery".to_string(),
+            usage: "[--split K-of-N] [--export DIR]".to_string(),
+            description: "Store a new recovery key in its own key slot and print it, or split it into N shares of which any K recover the wallet".to_string(),
+            examples: vec![
+                "wallet recovery".to_string(),
+                "wallet recovery --split 3-of-5 --export /media/usb".to_string(),
+            ],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "recover".to_string(),
+            usage: "[--force] [FILE...]".to_string(),
+            description: "Open the wallet with the recovery key or enough shares (typed in or read from files) and set a new master password, removing the old password slots".to_string(),
+            examples: vec![
+                "wallet recover".to_string(),
+                "wallet recover recovery-share-1.txt recovery-share-4.txt recovery-share-5.txt"
+                    .to_string(),
+            ],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "recipients list".to_string(),
+            usage: "".to_string(),
+            description: "List the age public keys that can open the wallet, without unlocking it"
+                .to_string(),
+            examples: vec!["wallet recipients list".to_string()],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "recipients add".to_string(),
+            usage: "<age1...>...".to_string(),
+            description: "Wrap the data key for each team member's public key so they can open the wallet with their own identity file".to_string(),
+            examples: vec!["wallet recipients add age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p".to_string()],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "recipients remove".to_string(),
+            usage: "<age1...>".to_string(),
+            description: "Remove a recipient and move the wallet to a new data key; asks for the password or recovery key of every other slot so each keeps working".to_string(),
+            examples: vec!["wallet recipients remove age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p".to_string()],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "--password-stdin".to_string(),
+            usage: "".to_string(),
+            description: "Read passwords as plain lines from stdin instead of the terminal"
+                .to_string(),
+            examples: vec!["printf 'secret\\n' | wallet --password-stdin -l".to_string()],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "--password-fd".to_string(),
+            usage: "<FD>".to_string(),
+            description: "Read passwords line by line from an open file descriptor".to_string(),
+            examples: vec!["wallet --password-fd 3 -s api-key 3< ~/.wallet-pass".to_string()],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "--password-file".to_string(),
+            usage: "<PATH>".to_string(),
+            description: "Read the password from a file only its owner can read".to_string(),
+            examples: vec!["wallet --password-file ~/.wallet-pass -l".to_string()],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "--password-env".to_string(),
+            usage: "".to_string(),
+            description: "Read the password from the WALLET_PASSWORD environment variable"
+                .to_string(),
+            examples: vec!["WALLET_PASSWORD=... wallet --password-env -l".to_string()],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "--keyfile".to_string(),
+            usage: "<FILE>".to_string(),
+            description: format!(
+                "Keyfile required to open the wallet, as a second factor (also {})",
+                keyfile::KEYFILE_ENV
+            ),
+            examples: vec!["wallet --keyfile /media/usb/team.key -s api-key".to_string()],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "--identity".to_string(),
+            usage: "<FILE>".to_string(),
+            description: format!(
+                "Open the wallet with an age identity file of one of its recipients instead of the password (also {})",
+                recipient::IDENTITY_ENV
+            ),
+            examples: vec!["wallet --identity ~/.config/wallet/identity.txt -s api-key".to_string()],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "--batch".to_string(),
+            usage: "".to_string(),
+            description: "Never prompt; exit with status 3 if no password source is given"
+                .to_string(),
+            examples: vec!["wallet --batch --password-file ~/.wallet-pass -s api-key".to_string()],
+        });
+
+        usage.add_command(CommandHelp {
+            flag: "--sandbox".to_string(),
+            usage: "".to_string(),
+            description: format!(
+                "Once unlocked, confine the process to the wallet's own directory and deny network access (Linux; also {}=1); the wallet must be alone in its directory, see {}",
+                sandbox::SANDBOX_ENV,
+                WALLET_FILE_ENV
+            ),
+            examples: vec![
+                "WALLET_FILE=~/.wallet/wallet.txt wallet --sandbox -s email".to_string(),
+            ],
+        });
+
         usage.add_command(CommandHelp {
             flag: "-h, --help".to_string(),
             usage: "".to_string(),
@@ -249,13 +1053,27 @@ impl Usage {
 
         // Footer
         println!("STORAGE:");
-        println!("    Data is stored in: ~/wallet.txt");
-        println!("    Each line follows the format: key:value");
+        println!(
+            "    Data is stored in: ~/wallet.txt, or the file named by {}",
+            WALLET_FILE_ENV
+        );
+        println!("    Entries (secret, username, URLs, notes, tags, custom fields)");
+        println!("    are serialized as JSON and encrypted with AES-256-GCM.");
         println!();
 
         println!("SECURITY NOTE:");
-        println!("    This tool stores data in plain text. For sensitive data,");
-        println!("    consider using additional encryption or a dedicated password manager.");
+        println!("    The entries are encrypted with a random 256-bit data key. That key");
+        println!(
+            "    is stored wrapped in up to {} key slots, any one of which unlocks",
+            header::MAX_SLOTS
+        );
+        println!("    the wallet: master passwords (stretched with Argon2id, 64 MiB and");
+        println!("    3 passes by default, see 'kdf upgrade'; optionally with a keyfile),");
+        println!("    a recovery key, and age recipients.");
+        println!("    'wallet agent' keeps unlocked data keys in locked memory, serves");
+        println!("    only your user over a private socket, and forgets them after the");
+        println!("    idle timeout or 'wallet lock'. Anyone who can run code as you");
+        println!("    while the agent holds a key can read the wallet.");
     }
 
     // Legacy method for backward compatibility
@@ -272,3 +1090,278 @@ impl Usage {
         usage
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_wallet(name: &str) -> Wallet {
+        let dir = std::env::temp_dir().join(format!("wallet-lib-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut wallet = Wallet::with_path(dir.join("wallet.txt").to_string_lossy().into_owned());
+        wallet.set_kdf(Kdf::Argon2id {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        });
+        wallet
+    }
+
+    #[test]
+    fn test_save_reuses_salt_until_rekey() {
+        let mut wallet = test_wallet("rekey");
+        wallet.init("pw").unwrap();
+        let salt = || {
+            let data = std::fs::read(wallet.file_path()).unwrap();
+            header::Header::parse(&data).unwrap().0.slots[0]
+                .salt
+                .clone()
+        };
+        let initial = salt();
+
+        let mut reopened = Wallet::with_path(wallet.file_path());
+        reopened.load("pw");
+        reopened.add("email".to_string(), "john@example.com".to_string());
+        // The password is not needed to save once the key is derived.
+        reopened.save("");
+        assert_eq!(salt(), initial);
+
+        reopened.rekey("pw").unwrap();
+        assert_ne!(salt(), initial);
+        let mut rekeyed = Wallet::with_path(wallet.file_path());
+        rekeyed.load("pw");
+        assert!(rekeyed.key_exists("email"));
+
+        std::fs::remove_dir_all(std::path::Path::new(wallet.file_path()).parent().unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_change_password() {
+        let mut wallet = test_wallet("passwd");
+        wallet.init("old").unwrap();
+        wallet.load("old");
+        wallet.add("email".to_string(), "john@example.com".to_string());
+        wallet.save("old");
+
+        wallet.change_password("new", None).unwrap();
+        assert!(!std::path::Path::new(&wallet.backup_path()).exists());
+
+        let data = std::fs::read(wallet.file_path()).unwrap();
+        assert!(crypto::decrypt(&data, "old").is_err());
+
+        let mut reopened = Wallet::with_path(wallet.file_path());
+        reopened.load("new");
+        assert_eq!(reopened.get(&"email".to_string()), Some("john@example.com"));
+
+        std::fs::remove_dir_all(std::path::Path::new(wallet.file_path()).parent().unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_keyfile_binding() {
+        let keyfile = Keyfile::from_bytes(b"usb stick");
+        let mut wallet = test_wallet("keyfile");
+        wallet.init("pw").unwrap();
+        wallet.load("pw");
+        wallet.add("email".to_string(), "john@example.com");
+        wallet.change_password("pw", Some(keyfile.clone())).unwrap();
+        assert!(wallet.requires_keyfile());
+
+        let mut reopened = Wallet::with_path(wallet.file_path());
+        let missing = reopened.try_load("pw").unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+        reopened.set_keyfile(Some(Keyfile::from_bytes(b"another stick")));
+        let wrong = reopened.try_load("pw").unwrap_err();
+        assert_eq!(wrong.kind(), io::ErrorKind::InvalidInput);
+        reopened.set_keyfile(Some(keyfile));
+        reopened.try_load("pw").unwrap();
+        assert_eq!(reopened.get(&"email".to_string()), Some("john@example.com"));
+
+        // Saving keeps the binding; removing it needs a password change.
+        reopened.save("");
+        assert!(reopened.requires_keyfile());
+        reopened.change_password("pw", None).unwrap();
+        assert!(!reopened.requires_keyfile());
+        Wallet::with_path(wallet.file_path())
+            .try_load("pw")
+            .unwrap();
+
+        std::fs::remove_dir_all(std::path::Path::new(wallet.file_path()).parent().unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_key_slots() {
+        let mut wallet = test_wallet("slots");
+        wallet.init("alice").unwrap();
+        wallet.add("email".to_string(), "john@example.com");
+        wallet.save("");
+        let kdf = *wallet.kdf();
+        assert_eq!(wallet.add_slot("bob", None, &kdf).unwrap(), 1);
+        assert!(wallet.rekey("alice").is_err());
+
+        let mut bob = Wallet::with_path(wallet.file_path());
+        bob.load("bob");
+        assert_eq!(bob.get(&"email".to_string()), Some("john@example.com"));
+        assert_eq!(bob.opened_slot(), Some(1));
+        assert!(bob.remove_slot(1).is_err());
+        bob.remove_slot(0).unwrap();
+        assert_eq!(bob.slots().len(), 1);
+        assert!(bob.remove_slot(0).is_err());
+
+        let err = Wallet::with_path(wallet.file_path())
+            .try_load("alice")
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        std::fs::remove_dir_all(std::path::Path::new(wallet.file_path()).parent().unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_recipients() {
+        let (alice, bob) = (
+            recipient::Identity::generate(),
+            recipient::Identity::generate(),
+        );
+        let mut wallet = test_wallet("recipients");
+        wallet.init("pw").unwrap();
+        wallet.add("email".to_string(), "john@example.com");
+        wallet.save("");
+        assert_eq!(wallet.add_recipient(&alice.to_public()).unwrap(), 1);
+        assert_eq!(wallet.add_recipient(&bob.to_public()).unwrap(), 2);
+        assert!(wallet.add_recipient(&bob.to_public()).is_err());
+
+        let mut member = Wallet::with_path(wallet.file_path());
+        member.try_load_with_identity(&alice).unwrap();
+        assert_eq!(member.get(&"email".to_string()), Some("john@example.com"));
+        assert!(member.change_password("new", None).is_err());
+
+        wallet
+            .remove_recipient(&bob.to_public().to_string(), |_, _| Ok(("pw".into(), None)))
+            .unwrap();
+        let err = Wallet::with_path(wallet.file_path())
+            .try_load_with_identity(&bob)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        Wallet::with_path(wallet.file_path())
+            .try_load_with_identity(&alice)
+            .unwrap();
+        Wallet::with_path(wallet.file_path()).load("pw");
+
+        std::fs::remove_dir_all(std::path::Path::new(wallet.file_path()).parent().unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_removing_a_recipient_keeps_every_other_slot() {
+        let (alice, bob) = (
+            recipient::Identity::generate(),
+            recipient::Identity::generate(),
+        );
+        let recovery = RecoveryKey::generate().unwrap();
+        let mut wallet = test_wallet("rotate");
+        wallet.init("pw").unwrap();
+        wallet.add("email".to_string(), "john@example.com");
+        wallet.save("");
+        wallet.set_recovery_key(&recovery).unwrap();
+        wallet.add_recipient(&alice.to_public()).unwrap();
+        wallet.add_recipient(&bob.to_public()).unwrap();
+        let bob_key = bob.to_public().to_string();
+
+        // Alice, unlocked with her identity, removes Bob.
+        let mut member = Wallet::with_path(wallet.file_path());
+        member.try_load_with_identity(&alice).unwrap();
+        let secret = |_: usize, slot: &Slot| match slot.kind {
+            SlotKind::Recovery { .. } => Ok((recovery.passphrase(), None)),
+            _ => Ok(("pw".into(), None)),
+        };
+        assert!(
+            member
+                .remove_recipient(&bob_key, |_, _| Ok(("wrong".into(), None)))
+                .is_err()
+        );
+        Wallet::with_path(wallet.file_path())
+            .try_load_with_identity(&bob)
+            .unwrap();
+        member.remove_recipient(&bob_key, secret).unwrap();
+        assert_eq!(member.slots().len(), 3);
+
+        let err = Wallet::with_path(wallet.file_path())
+            .try_load_with_identity(&bob)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        for password in ["pw".into(), recovery.passphrase()] {
+            let mut opened = Wallet::with_path(wallet.file_path());
+            opened.try_load(&password).unwrap();
+            assert_eq!(opened.get(&"email".to_string()), Some("john@example.com"));
+        }
+        let mut opened = Wallet::with_path(wallet.file_path());
+        opened.try_load_with_identity(&alice).unwrap();
+        assert_eq!(opened.get(&"email".to_string()), Some("john@example.com"));
+
+        std::fs::remove_dir_all(std::path::Path::new(wallet.file_path()).parent().unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_recovery_key_resets_password() {
+        let mut wallet = test_wallet("recovery");
+        wallet.init("pw").unwrap();
+        wallet.add("email".to_string(), "john@example.com");
+        wallet.save("");
+        let kdf = *wallet.kdf();
+        wallet.add_slot("other", None, &kdf).unwrap();
+        let recovery = RecoveryKey::generate().unwrap();
+        assert_eq!(wallet.set_recovery_key(&recovery).unwrap(), 2);
+        // A new recovery key replaces the old one.
+        let recovery = RecoveryKey::generate().unwrap();
+        assert_eq!(wallet.set_recovery_key(&recovery).unwrap(), 2);
+
+        let mut recovered = Wallet::with_path(wallet.file_path());
+        recovered.load(&recovery.passphrase());
+        assert!(recovered.change_password("new", None).is_err());
+        assert_eq!(recovered.reset_password("new").unwrap(), 2);
+        assert!(matches!(
+            recovered.slots(),
+            [
+                Slot {
+                    kind: SlotKind::Recovery { .. },
+                    ..
+                },
+                Slot {
+                    kind: SlotKind::Password { .. },
+                    ..
+                }
+            ]
+        ));
+
+        for old in ["pw", "other"] {
+            assert!(Wallet::with_path(wallet.file_path()).try_load(old).is_err());
+        }
+        let mut reopened = Wallet::with_path(wallet.file_path());
+        reopened.load("new");
+        assert_eq!(reopened.get(&"email".to_string()), Some("john@example.com"));
+
+        std::fs::remove_dir_all(std::path::Path::new(wallet.file_path()).parent().unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_init_refuses_to_overwrite() {
+        let mut wallet = test_wallet("init");
+        assert!(!wallet.exists());
+        wallet.init("first").unwrap();
+        assert!(wallet.exists());
+
+        let err = wallet.init("second").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+        let data = std::fs::read(wallet.file_path()).unwrap();
+        assert!(crypto::decrypt(&data, "first").is_ok());
+
+        std::fs::remove_dir_all(std::path::Path::new(wallet.file_path()).parent().unwrap())
+            .unwrap();
+    }
+}
@@ -1,9 +1,29 @@
-use std::io::{self, Write};
+use std::path::PathBuf;
 
+use wallet::prompt::{self, PasswordSource};
 use wallet::*;
 
+/// Exit status used when `--batch` is given without a password source.
+const EXIT_NO_PASSWORD_SOURCE: i32 = 3;
+/// Exit status used when `audit`, `breach-check` or `doctor` report problems.
+const EXIT_FINDINGS: i32 = 4;
+
 fn main() {
-    let cmd = Command::new();
+    // Before anything secret is read into memory.
+    harden::harden_process();
+
+    let mut cmd = Command::new();
+    let batch = cmd.take_flag("--batch");
+    let sandboxed = sandbox::requested(cmd.take_flag("--sandbox"));
+    let source = password_source(&mut cmd, batch);
+    let keyfile_path = cmd
+        .take_option("--keyfile")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os(keyfile::KEYFILE_ENV).map(PathBuf::from));
+    let identity_path = cmd
+        .take_option("--identity")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os(recipient::IDENTITY_ENV).map(PathBuf::from));
     let usage = Usage::default();
     let mut wallet = Wallet::new();
 
@@ -12,26 +32,105 @@ fn main() {
         return;
     }
 
-    let mut password: String = String::new();
-    print!("Enter wallet password: ");
-    io::stdout().flush().expect("Failed to flush stdout");
-    io::stdin()
-        .read_line(&mut password)
-        .expect("Failed to read password");
-    let password = password.trim();
+    match cmd.args[1].as_str() {
+        "-h" | "--help" => {
+            usage.show();
+            return;
+        }
+        "init" => {
+            load_keyfile(&mut wallet, &keyfile_path);
+            init_wallet(&mut wallet, &cmd.args[2..], &source);
+            return;
+        }
+        "keyfile" => {
+            new_keyfile(&cmd.args[2..]);
+            return;
+        }
+        "identity" => {
+            new_identity(&cmd.args[2..]);
+            return;
+        }
+        "generate" if !cmd.args.iter().any(|arg| arg == "--store") => {
+            generate_password(None, &cmd.args[2..]);
+            return;
+        }
+        "breach-check" if cmd.args.get(2).is_some_and(|arg| arg == "--build-index") => {
+            build_breach_index(&cmd.args[3..]);
+            return;
+        }
+        #[cfg(unix)]
+        "agent" => {
+            run_agent(&cmd.args[2..]);
+            return;
+        }
+        #[cfg(unix)]
+        "lock" => {
+            lock_agent();
+            return;
+        }
+        "doctor" => {
+            run_doctor(&wallet);
+            return;
+        }
+        "recover" => {
+            recover_wallet(&mut wallet, &cmd.args[2..], &source);
+            return;
+        }
+        "slot" if cmd.args.get(2).map(String::as_str) == Some("list") => {
+            list_slots(&wallet);
+            return;
+        }
+        "recipients" if cmd.args.get(2).map(String::as_str) == Some("list") => {
+            list_recipients(&wallet);
+            return;
+        }
+        "inspect" => {
+            let path = cmd
+                .args
+                .get(2)
+                .map(String::as_str)
+                .unwrap_or(wallet.file_path());
+            match crypto::inspect_file(path) {
+                Ok(header) => {
+                    println!("{}", path);
+                    println!("{}", header);
+                }
+                Err(e) => {
+                    eprintln!("Error: Cannot inspect '{}': {}", path, e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        _ => {}
+    }
+
+    if !wallet.exists() {
+        eprintln!(
+            "Error: No wallet found at {}. Run 'wallet init' to create one.",
+            wallet.file_path()
+        );
+        std::process::exit(1);
+    }
 
-    wallet.load(password);
     let command = &cmd.args[1];
+    load_keyfile(&mut wallet, &keyfile_path);
+    let session = match &identity_path {
+        Some(path) => unlock_with_identity(&mut wallet, path),
+        None => unlock_wallet(&mut wallet, command, &source),
+    };
+    if sandboxed {
+        enter_sandbox(&wallet, command, &cmd.args[2..]);
+    }
     match command.as_str() {
         "-a" | "--add" => {
-            if cmd.args.len() >= 4 {
-                let key = cmd.args[2].clone();
-                let value = cmd.args[3].clone();
-                wallet.add(key.clone(), value.clone());
-                println!("✓ Added '{}' to wallet", key);
+            if cmd.args.len() >= 3 {
+                add_entry(&mut wallet, &cmd.args[2..], &source, batch);
             } else {
                 eprintln!("Error: Missing arguments for add command");
-                eprintln!("Usage: wallet -a <KEY> <VALUE>");
+                eprintln!(
+                    "Usage: wallet -a <KEY> [VALUE | --stdin | --paste [--clear-clipboard]] [--user NAME] [--url URL] [--note TEXT] [--tag TAG] [--field NAME=VALUE]"
+                );
                 std::process::exit(1);
             }
         }
@@ -65,10 +164,11 @@ fn main() {
         "-s" | "--show" => {
             if cmd.args.len() >= 3 {
                 let key = cmd.args[2].clone();
-                match wallet.get(&key) {
-                    Some(value) => {
+                match wallet.entry(&key) {
+                    Some(entry) => {
                         println!("{}:", key);
-                        println!("{}", value);
+                        println!("{}", &*entry.secret);
+                        print_entry_details(entry);
                     }
                     None => {
                         eprintln!("Error: Key '{}' not found in wallet", key);
@@ -97,8 +197,91 @@ fn main() {
                 std::process::exit(1);
             }
         }
-        "-h" | "--help" => {
-            usage.show();
+        "generate" => {
+            generate_password(Some(&mut wallet), &cmd.args[2..]);
+        }
+        "otp" => {
+            show_otp(&mut wallet, &cmd.args[2..]);
+        }
+        "import-otp" => {
+            import_otp(&mut wallet, &cmd.args[2..]);
+        }
+        "audit" => {
+            run_audit(&wallet, &cmd.args[2..]);
+        }
+        "breach-check" => {
+            run_breach_check(&wallet, &cmd.args[2..]);
+        }
+        "passwd" => {
+            if let Session::Identity = session {
+                eprintln!("Error: The wallet was unlocked with an identity, which has no password");
+                std::process::exit(1);
+            }
+            let mut options = cmd.args[2..].to_vec();
+            let force = take_arg(&mut options, "--force");
+            let keyfile = if take_arg(&mut options, "--no-keyfile") {
+                None
+            } else if let Some(index) = options.iter().position(|arg| arg == "--new-keyfile") {
+                let Some(path) = options.get(index + 1) else {
+                    eprintln!("Error: --new-keyfile needs a file");
+                    std::process::exit(1);
+                };
+                Some(read_keyfile(std::path::Path::new(path)))
+            } else {
+                wallet.keyfile().cloned()
+            };
+            let new_password = read_new_password(&source, force);
+            let bound = keyfile.is_some();
+            match wallet.change_password(&new_password, keyfile) {
+                Ok(()) if bound => println!("✓ Wallet password changed; the keyfile is required"),
+                Ok(()) => println!("✓ Wallet password changed"),
+                Err(e) => {
+                    eprintln!("Error: Failed to change password: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        "rekey" => {
+            if let Err(e) = session.rekey(&mut wallet) {
+                eprintln!("Error: Failed to rekey wallet: {}", e);
+                std::process::exit(1);
+            }
+            println!("✓ Wallet re-encrypted under a new data key and salt");
+            return;
+        }
+        "slot" => {
+            manage_slots(&mut wallet, &cmd.args[2..], &source);
+            return;
+        }
+        "recovery" => {
+            create_recovery_key(&mut wallet, &cmd.args[2..]);
+            return;
+        }
+        "recipients" => {
+            manage_recipients(&mut wallet, &cmd.args[2..], &session, &source);
+            return;
+        }
+        "kdf" => {
+            if cmd.args.get(2).map(String::as_str) != Some("upgrade") {
+                eprintln!("Error: Unknown kdf subcommand");
+                eprintln!(
+                    "Usage: wallet kdf upgrade [--memory KIB] [--iterations N] [--parallelism P]"
+                );
+                std::process::exit(1);
+            }
+            if let Session::Identity = session {
+                eprintln!("Error: The wallet was unlocked with an identity, which has no KDF");
+                std::process::exit(1);
+            }
+            let kdf = parse_kdf_options(&cmd.args[3..]);
+            if let Err(e) = crypto::validate_kdf(&kdf) {
+                eprintln!("Error: Invalid KDF parameters: {}", e);
+                std::process::exit(1);
+            }
+            println!("Old KDF: {}", wallet.kdf());
+            wallet.set_kdf(kdf);
+            println!("✓ Re-encrypting wallet with {}", kdf);
         }
         _ => {
             eprintln!("Error: Unknown command '{}'", command);
@@ -107,5 +290,1417 @@ fn main() {
         }
     }
 
-    wallet.save(password);
+    session.save(&mut wallet);
+}
+
+/// How the wallet was unlocked, which decides how it is written back.
+enum Session {
+    Password(SecretString),
+    /// Unlocked with `--identity`; saves reuse the unwrapped data key.
+    Identity,
+    #[cfg(unix)]
+    Agent(agent::Client),
+}
+
+impl Session {
+    fn rekey(&self, wallet: &mut Wallet) -> std::io::Result<()> {
+        match self {
+            Session::Password(password) => wallet.rekey(password),
+            Session::Identity => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "The wallet was unlocked with an identity; rekey needs the password",
+            )),
+            #[cfg(unix)]
+            Session::Agent(_) => unreachable!("rekey never unlocks through the agent"),
+        }
+    }
+
+    fn save(&self, wallet: &mut Wallet) {
+        match self {
+            Session::Password(password) => wallet.save(password),
+            // The KDF never changes in this session, so no password is needed.
+            Session::Identity => wallet.save(""),
+            #[cfg(unix)]
+            Session::Agent(client) => {
+                if let Err(e) = client.store(wallet.file_path(), &wallet.serialize()) {
+                    eprintln!("Error: Cannot save wallet through the agent: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+/// Loads the wallet through the agent when [`agent::SOCK_ENV`] is set, and
+/// otherwise by asking for the password.
+fn unlock_wallet(wallet: &mut Wallet, command: &str, source: &Option<PasswordSource>) -> Session {
+    // Fail before prompting rather than after a wasted password attempt.
+    if wallet.keyfile().is_none() && wallet.requires_keyfile() {
+        eprintln!(
+            "Error: This wallet requires a keyfile; pass --keyfile FILE or set {}",
+            keyfile::KEYFILE_ENV
+        );
+        std::process::exit(1);
+    }
+
+    // Changing the password, salt, KDF or key slots needs the unwrapped
+    // data key, which the agent never hands out.
+    #[cfg(unix)]
+    if !matches!(
+        command,
+        "passwd" | "rekey" | "kdf" | "slot" | "recovery" | "recipients"
+    ) && let Some(client) = agent::Client::from_env()
+    {
+        match unlock_with_agent(wallet, &client, source) {
+            Ok(()) => return Session::Agent(client),
+            Err(e) => eprintln!("Warning: {}; continuing without the agent", e),
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = command;
+
+    let unlock_source = require_source(source);
+    match prompt::unlock_with_retry("Enter wallet password: ", unlock_source, |password| {
+        wallet.try_load(password)
+    }) {
+        Ok((password, ())) => Session::Password(password),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Loads the wallet with the identity file given with `--identity` or
+/// [`recipient::IDENTITY_ENV`], without asking for a password.
+fn unlock_with_identity(wallet: &mut Wallet, path: &std::path::Path) -> Session {
+    let identity = recipient::read_identity(path).unwrap_or_else(|e| {
+        eprintln!("Error: Cannot read identity '{}': {}", path.display(), e);
+        std::process::exit(1);
+    });
+    if let Err(e) = wallet.try_load_with_identity(&identity) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    Session::Identity
+}
+
+/// Uses the agent's cached key, asking for the password only when the agent
+/// is locked.
+#[cfg(unix)]
+fn unlock_with_agent(
+    wallet: &mut Wallet,
+    client: &agent::Client,
+    source: &Option<PasswordSource>,
+) -> std::io::Result<()> {
+    let path = wallet.file_path().to_string();
+    let keyfile = wallet.keyfile().cloned();
+    let plaintext = match client.load(&path)? {
+        Some(plaintext) => plaintext,
+        None => {
+            let unlock_source = require_source(source);
+            match prompt::unlock_with_retry("Enter wallet password: ", unlock_source, |password| {
+                client.unlock(&path, password, keyfile.as_ref())
+            }) {
+                Ok((_, plaintext)) => plaintext,
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidInput => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    };
+    wallet.restore(&plaintext).map(|_| ())
+}
+
+/// Handles `wallet -a <KEY> [VALUE] [options]`.
+///
+/// Without a VALUE argument the secret comes from `--stdin`, from the
+/// clipboard with `--paste`, or from a hidden prompt asked twice, so it never
+/// has to appear on the command line.
+fn add_entry(wallet: &mut Wallet, args: &[String], source: &Option<PasswordSource>, batch: bool) {
+    let key = args[0].clone();
+    let mut options: Vec<String> = args[1..].to_vec();
+    // `--` makes the next argument the value even if it looks like an option.
+    let dashed_value = if options.first().is_some_and(|arg| arg == "--") {
+        options.remove(0);
+        if options.is_empty() {
+            eprintln!("Error: Missing value after '--'");
+            std::process::exit(1);
+        }
+        Some(options.remove(0))
+    } else {
+        None
+    };
+    let from_stdin = take_arg(&mut options, "--stdin");
+    let from_clipboard = take_arg(&mut options, "--paste");
+    let clear_clipboard = take_arg(&mut options, "--clear-clipboard");
+    let inline_value = dashed_value.or_else(|| match options.first() {
+        Some(value) if !value.starts_with("--") => Some(options.remove(0)),
+        _ => None,
+    });
+
+    if [inline_value.is_some(), from_stdin, from_clipboard]
+        .iter()
+        .filter(|&&given| given)
+        .count()
+        > 1
+    {
+        eprintln!("Error: Give the value only once: as an argument, --stdin or --paste");
+        std::process::exit(1);
+    }
+    if clear_clipboard && !from_clipboard {
+        eprintln!("Error: --clear-clipboard can only be used with --paste");
+        std::process::exit(1);
+    }
+
+    let value = if let Some(value) = inline_value {
+        SecretString::new(value)
+    } else if from_stdin {
+        prompt::read_secret_stdin().unwrap_or_else(|e| {
+            eprintln!("Error: Failed to read value from stdin: {}", e);
+            std::process::exit(1);
+        })
+    } else if from_clipboard {
+        clipboard::Clipboard::paste().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        })
+    } else if batch {
+        eprintln!("Error: No value given; use --stdin or --paste in batch mode");
+        std::process::exit(1);
+    } else {
+        read_secret_value(&key, source)
+    };
+
+    if value.is_empty() {
+        eprintln!("Error: Refusing to store an empty value for '{}'", key);
+        std::process::exit(1);
+    }
+
+    let strength = strength::estimate(&value, &[&key]);
+    if strength.is_weak() {
+        eprintln!("Warning: The value for '{}' is {}", key, strength);
+        print_suggestions(&strength);
+    }
+    warn_if_breached(&key, &value);
+
+    wallet.add(key.clone(), value);
+    if let Some(entry) = wallet.entry_mut(&key) {
+        apply_entry_options(entry, &options);
+    }
+    println!("✓ Added '{}' to wallet", key);
+
+    if clear_clipboard {
+        match clipboard::Clipboard::clear() {
+            Ok(()) => println!("✓ Cleared clipboard"),
+            Err(e) => eprintln!("Warning: {}", e),
+        }
+    }
+}
+
+/// Removes `flag` from `args`, returning whether it was present.
+fn take_arg(args: &mut Vec<String>, flag: &str) -> bool {
+    let before = args.len();
+    args.retain(|arg| arg != flag);
+    args.len() != before
+}
+
+/// Asks for a secret value twice without echo.
+///
+/// Uses stdin when passwords come from stdin, the terminal otherwise.
+fn read_secret_value(key: &str, source: &Option<PasswordSource>) -> SecretString {
+    let value_source = match source {
+        Some(PasswordSource::Stdin) => PasswordSource::Stdin,
+        _ => PasswordSource::Tty,
+    };
+    let read = |prompt: &str| {
+        prompt::read_password(prompt, &value_source).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        })
+    };
+
+    let value = read(&format!("Enter value for '{}': ", key));
+    let confirmation = read("Confirm value: ");
+    if value != confirmation {
+        eprintln!("Error: Values do not match");
+        std::process::exit(1);
+    }
+    value
+}
+
+/// Applies `--user`, `--url`, `--note`, `--tag` and `--field` options to an entry.
+fn apply_entry_options(entry: &mut Entry, options: &[String]) {
+    let mut iter = options.iter();
+    while let Some(option) = iter.next() {
+        let Some(value) = iter.next() else {
+            eprintln!("Error: Missing value for option '{}'", option);
+            std::process::exit(1);
+        };
+        match option.as_str() {
+            "--user" => entry.username = Some(value.clone()),
+            "--url" => entry.urls.push(value.clone()),
+            "--note" => entry.notes = Some(value.clone()),
+            "--tag" => entry.tags.push(value.clone()),
+            "--otp" => match otp::OtpSecret::parse(value) {
+                Ok(_) => entry.otp = Some(SecretString::from(value.as_str())),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            },
+            "--field" => match value.split_once('=') {
+                Some((name, field)) => {
+                    entry.fields.insert(name.to_string(), field.to_string());
+                }
+                None => {
+                    eprintln!("Error: Custom fields must be given as NAME=VALUE");
+                    std::process::exit(1);
+                }
+            },
+            _ => {
+                eprintln!("Error: Unknown option '{}' for add command", option);
+                std::process::exit(1);
+            }
+        }
+    }
+    entry.touch();
+}
+
+/// Prints the non-secret metadata of an entry below its value.
+fn print_entry_details(entry: &Entry) {
+    if let Some(username) = &entry.username {
+        println!("  username: {}", username);
+    }
+    for url in &entry.urls {
+        println!("  url:      {}", url);
+    }
+    if !entry.tags.is_empty() {
+        println!("  tags:     {}", entry.tags.join(", "));
+    }
+    if let Some(notes) = &entry.notes {
+        println!("  notes:    {}", notes);
+    }
+    for (name, value) in &entry.fields {
+        println!("  {}: {}", name, value);
+    }
+    if entry.otp.is_some() {
+        println!(
+            "  otp:      stored; show a code with 'wallet otp {}'",
+            entry.name
+        );
+    }
+}
+
+/// Builds KDF settings from `kdf upgrade` options, starting from the defaults.
+fn parse_kdf_options(options: &[String]) -> Kdf {
+    let mut kdf = Kdf::default();
+    let mut iter = options.iter();
+    while let Some(option) = iter.next() {
+        let value = match iter.next().map(|v| v.parse::<u32>()) {
+            Some(Ok(value)) => value,
+            _ => {
+                eprintln!("Error: Option '{}' needs a numeric value", option);
+                std::process::exit(1);
+            }
+        };
+        match (option.as_str(), &mut kdf) {
+            ("--pbkdf2", _) => kdf = Kdf::Pbkdf2Sha256 { iterations: value },
+            ("--memory", Kdf::Argon2id { memory_kib, .. }) => *memory_kib = value,
+            ("--iterations", Kdf::Argon2id { iterations, .. }) => *iterations = value,
+            ("--iterations", Kdf::Pbkdf2Sha256 { iterations }) => *iterations = value,
+            ("--parallelism", Kdf::Argon2id { parallelism, .. }) => *parallelism = value,
+            _ => {
+                eprintln!("Error: Unknown or conflicting KDF option '{}'", option);
+                std::process::exit(1);
+            }
+        }
+    }
+    kdf.checked().unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Prompts for a password, exiting the process if it cannot be read.
+fn read_password(prompt: &str, source: &Option<PasswordSource>) -> SecretString {
+    match prompt::read_password(prompt, require_source(source)) {
+        Ok(password) => password,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Creates a new wallet after asking for the master password twice.
+fn init_wallet(wallet: &mut Wallet, options: &[String], source: &Option<PasswordSource>) {
+    if wallet.exists() {
+        eprintln!("Error: A wallet already exists at {}", wallet.file_path());
+        std::process::exit(1);
+    }
+
+    let mut options = options.to_vec();
+    let force = take_arg(&mut options, "--force");
+    let kdf = parse_kdf_options(&options);
+    if let Err(e) = crypto::validate_kdf(&kdf) {
+        eprintln!("Error: Invalid KDF parameters: {}", e);
+        std::process::exit(1);
+    }
+    wallet.set_kdf(kdf);
+
+    let password = read_new_password(source, force);
+    match wallet.init(&password) {
+        Ok(()) => {
+            println!("✓ Created wallet at {}", wallet.file_path());
+            println!("  KDF: {}", kdf);
+            if wallet.keyfile().is_some() {
+                println!("  Keyfile: required to open the wallet; keep a backup of it");
+            }
+        }
+        Err(e) => {
+            eprintln!("Error: Failed to create wallet: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Asks for a new master password twice and checks that it is acceptable.
+///
+/// Weak passwords are refused unless `force` is set.
+fn read_new_password(source: &Option<PasswordSource>, force: bool) -> SecretString {
+    let password = read_password("Enter new wallet password: ", source);
+    let confirmation = read_password("Confirm new wallet password: ", source);
+    if password != confirmation {
+        eprintln!("Error: Passwords do not match");
+        std::process::exit(1);
+    }
+
+    let strength = strength::estimate(&password, &["wallet"]);
+    if password.is_empty() || (strength.is_weak() && !force) {
+        eprintln!("Error: Master password is {}", strength);
+        print_suggestions(&strength);
+        if !password.is_empty() {
+            eprintln!("Use --force to accept it anyway");
+        }
+        std::process::exit(1);
+    }
+    if strength.is_weak() {
+        eprintln!("Warning: Master password is {}", strength);
+    }
+    password
+}
+
+fn print_suggestions(strength: &strength::Strength) {
+    for suggestion in &strength.suggestions {
+        eprintln!("  - {}", suggestion);
+    }
+}
+
+/// Removes the global password options from the arguments.
+///
+/// Returns `None` in `--batch` mode when no non-prompting source was given.
+fn password_source(cmd: &mut Command, batch: bool) -> Option<PasswordSource> {
+    let mut sources = Vec::new();
+    if let Some(fd) = cmd.take_option("--password-fd") {
+        match fd.parse() {
+            Ok(fd) => sources.push(PasswordSource::Fd(fd)),
+            Err(_) => {
+                eprintln!("Error: Invalid file descriptor '{}'", fd);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(path) = cmd.take_option("--password-file") {
+        sources.push(PasswordSource::File(PathBuf::from(path)));
+    }
+    if cmd.take_flag("--password-env") {
+        sources.push(PasswordSource::Env);
+    }
+    if cmd.take_flag("--password-stdin") {
+        sources.push(PasswordSource::Stdin);
+    }
+
+    if sources.len() > 1 {
+        eprintln!("Error: Only one password source option may be given");
+        std::process::exit(1);
+    }
+    match sources.pop() {
+        Some(source) => Some(source),
+        None if batch => None,
+        None => Some(PasswordSource::Tty),
+    }
+}
+
+/// Returns the password source, or exits if `--batch` left none available.
+fn require_source(source: &Option<PasswordSource>) -> &PasswordSource {
+    match source {
+        Some(source) => source,
+        None => {
+            eprintln!(
+                "Error: A password is required but --batch forbids prompting; use --password-fd, --password-file or --password-env"
+            );
+            std::process::exit(EXIT_NO_PASSWORD_SOURCE);
+        }
+    }
+}
+
+/// Handles `wallet generate`. The password is only printed when it is
+/// neither stored (`--store KEY`, requires an unlocked wallet) nor copied.
+/// With `--passphrase` a diceware passphrase is generated instead.
+fn generate_password(wallet: Option<&mut Wallet>, options: &[String]) {
+    let mut policy = PasswordPolicy::default();
+    let mut passphrase_policy = PassphrasePolicy::default();
+    let mut passphrase = false;
+    let mut wordlist_path = None;
+    let mut store = None;
+    let mut copy = false;
+
+    let number = |option: &str, value: String| -> usize {
+        value.parse().unwrap_or_else(|_| {
+            eprintln!("Error: {} needs a number", option);
+            std::process::exit(1);
+        })
+    };
+
+    let mut iter = options.iter();
+    while let Some(option) = iter.next() {
+        let mut value = |option: &str| match iter.next() {
+            Some(value) => value.clone(),
+            None => {
+                eprintln!("Error: Missing value for option '{}'", option);
+                std::process::exit(1);
+            }
+        };
+        match option.as_str() {
+            "--length" => policy.length = number(option, value(option)),
+            "--no-lower" => policy.lowercase = false,
+            "--no-upper" => policy.uppercase = false,
+            "--no-digits" => policy.digits = false,
+            "--no-symbols" => policy.symbols = None,
+            "--symbols" => policy.symbols = Some(value(option)),
+            "--no-look-alikes" => policy.exclude_look_alikes = true,
+            "--pronounceable" => policy.pronounceable = true,
+            "--any-classes" => policy.require_each_class = false,
+            "--passphrase" => passphrase = true,
+            "--words" => passphrase_policy.words = number(option, value(option)),
+            "--separator" => passphrase_policy.separator = value(option),
+            "--capitalize" => {
+                passphrase_policy.capitalization = value(option).parse().unwrap_or_else(|e| {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                })
+            }
+            "--add-digits" => passphrase_policy.digits = number(option, value(option)),
+            "--wordlist" => wordlist_path = Some(value(option)),
+            "--store" => store = Some(value(option)),
+            "--copy" => copy = true,
+            _ => {
+                eprintln!("Error: Unknown option '{}' for generate", option);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let fail = |e: GeneratorError| -> ! {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    };
+    let password = if passphrase {
+        let wordlist = match &wordlist_path {
+            Some(path) => passphrase::Wordlist::from_file(path).unwrap_or_else(|e| {
+                eprintln!("Error: Cannot load wordlist '{}': {}", path, e);
+                std::process::exit(1);
+            }),
+            None => passphrase::Wordlist::bundled(),
+        };
+        let generated =
+            passphrase::generate(&passphrase_policy, &wordlist).unwrap_or_else(|e| fail(e));
+        eprintln!(
+            "Entropy: {:.1} bits ({} words from a {}-word list)",
+            generated.entropy_bits,
+            passphrase_policy.words,
+            wordlist.len()
+        );
+        generated.phrase
+    } else {
+        generator::generate(&policy).unwrap_or_else(|e| fail(e))
+    };
+
+    let key = match (wallet, &store) {
+        (Some(wallet), Some(key)) => {
+            wallet.add(key.clone(), password.clone());
+            Some(key)
+        }
+        _ => None,
+    };
+
+    if let Some(key) = key {
+        println!("✓ Stored generated password as '{}'", key);
+    }
+    if copy {
+        match clipboard::Clipboard::copy(&password) {
+            Ok(()) => println!("✓ Copied generated password to clipboard"),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    if key.is_none() && !copy {
+        println!("{}", &*password);
+    }
+}
+
+/// Confines the process to the wallet's directory, plus the files given to
+/// `command`, and cuts off the network. Exits if the sandbox is unavailable
+/// rather than silently running without it.
+fn enter_sandbox(wallet: &Wallet, command: &str, args: &[String]) {
+    let mut sandbox = sandbox::Sandbox::for_wallet(wallet.file_path()).unwrap_or_else(|e| {
+        eprintln!("Error: Cannot enable the sandbox: {}", e);
+        std::process::exit(1);
+    });
+    if let Some(db) = std::env::var_os(breach::DB_ENV) {
+        sandbox.allow_read(db);
+    }
+    match command {
+        "import-otp" => {
+            for source in args.iter().filter(|arg| !arg.starts_with('-')) {
+                sandbox.allow_read(source);
+            }
+        }
+        "recovery" => {
+            for pair in args.windows(2) {
+                if pair[0] == "--export" {
+                    sandbox.allow_write(&pair[1]);
+                }
+            }
+        }
+        "breach-check" | "generate" | "passwd" | "slot" => {
+            for pair in args.windows(2) {
+                if matches!(pair[0].as_str(), "--db" | "--wordlist" | "--new-keyfile") {
+                    sandbox.allow_read(&pair[1]);
+                }
+            }
+        }
+        _ => {}
+    }
+    if let Err(e) = sandbox.apply() {
+        eprintln!("Error: Cannot enable the sandbox: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Reads the keyfile given with `--keyfile` or [`keyfile::KEYFILE_ENV`]
+/// into the wallet.
+fn load_keyfile(wallet: &mut Wallet, path: &Option<PathBuf>) {
+    if let Some(path) = path {
+        wallet.set_keyfile(Some(read_keyfile(path)));
+    }
+}
+
+fn read_keyfile(path: &std::path::Path) -> Keyfile {
+    Keyfile::read(path).unwrap_or_else(|e| {
+        eprintln!("Error: Cannot read keyfile '{}': {}", path.display(), e);
+        std::process::exit(1);
+    })
+}
+
+/// Handles `wallet slot list`, which only reads the file header.
+fn list_slots(wallet: &Wallet) {
+    let header = crypto::inspect_file(wallet.file_path()).unwrap_or_else(|e| {
+        eprintln!("Error: Cannot read '{}': {}", wallet.file_path(), e);
+        std::process::exit(1);
+    });
+    println!("Key slots in {}:", wallet.file_path());
+    for (index, slot) in header.slots.iter().enumerate() {
+        println!("  {}: {}", index, slot);
+    }
+    if header.version < header::FORMAT_VERSION {
+        println!("  The file predates key slots; it is converted when next unlocked.");
+    }
+}
+
+/// Handles `wallet slot add` and `wallet slot remove <N>`.
+fn manage_slots(wallet: &mut Wallet, args: &[String], source: &Option<PasswordSource>) {
+    let usage = "Usage: wallet slot list | slot add [--force] [--new-keyfile FILE] [KDF options] | slot remove <N>";
+    match args.first().map(String::as_str) {
+        Some("add") => {
+            let mut options = args[1..].to_vec();
+            let force = take_arg(&mut options, "--force");
+            let keyfile = match options.iter().position(|arg| arg == "--new-keyfile") {
+                Some(index) => {
+                    let Some(path) = options.get(index + 1).cloned() else {
+                        eprintln!("Error: --new-keyfile needs a file");
+                        std::process::exit(1);
+                    };
+                    options.drain(index..index + 2);
+                    Some(read_keyfile(std::path::Path::new(&path)))
+                }
+                None => None,
+            };
+            let kdf = if options.is_empty() {
+                *wallet.kdf()
+            } else {
+                parse_kdf_options(&options)
+            };
+            if let Err(e) = crypto::validate_kdf(&kdf) {
+                eprintln!("Error: Invalid KDF parameters: {}", e);
+                std::process::exit(1);
+            }
+
+            let password = read_new_password(source, force);
+            match wallet.add_slot(&password, keyfile.as_ref(), &kdf) {
+                Ok(index) => println!("✓ Added key slot {}: {}", index, wallet.slots()[index]),
+                Err(e) => {
+                    eprintln!("Error: Cannot add key slot: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some("remove") => {
+            let Some(index) = args.get(1).and_then(|arg| arg.parse::<usize>().ok()) else {
+                eprintln!("Error: Missing or invalid slot number");
+                eprintln!("{}", usage);
+                std::process::exit(1);
+            };
+            match wallet.remove_slot(index) {
+                Ok(()) => {
+                    println!("✓ Removed key slot {}", index);
+                    println!("  Copies of the wallet file made before now still open with it.");
+                }
+                Err(e) => {
+                    eprintln!("Error: Cannot remove key slot: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ => {
+            eprintln!("Error: Unknown slot subcommand");
+            eprintln!("{}", usage);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Handles `wallet recipients list`, which only reads the file header.
+fn list_recipients(wallet: &Wallet) {
+    let header = crypto::inspect_file(wallet.file_path()).unwrap_or_else(|e| {
+        eprintln!("Error: Cannot read '{}': {}", wallet.file_path(), e);
+        std::process::exit(1);
+    });
+    println!("Recipients of {}:", wallet.file_path());
+    let mut any = false;
+    for (index, slot) in header.slots.iter().enumerate() {
+        if let Some(recipient) = slot.recipient() {
+            println!("  {}: {}", index, recipient);
+            any = true;
+        }
+    }
+    if !any {
+        println!("  None; add one with 'wallet recipients add age1...'");
+    }
+}
+
+/// Handles `wallet recipients add <age1...>...` and
+/// `wallet recipients remove <age1...>`, which asks for the secret of every
+/// password and recovery slot so none is lost when the data key changes.
+fn manage_recipients(
+    wallet: &mut Wallet,
+    args: &[String],
+    session: &Session,
+    source: &Option<PasswordSource>,
+) {
+    let usage =
+        "Usage: wallet recipients list | recipients add <age1...>... | recipients remove <age1...>";
+    let options = args.get(1..).unwrap_or_default();
+    match args.first().map(String::as_str) {
+        Some("add") if !options.is_empty() => {
+            let recipients: Vec<recipient::Recipient> = options
+                .iter()
+                .map(|text| {
+                    recipient::parse_recipient(text).unwrap_or_else(|e| {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    })
+                })
+                .collect();
+            for recipient in &recipients {
+                match wallet.add_recipient(recipient) {
+                    Ok(index) => println!("✓ Added key slot {}: {}", index, wallet.slots()[index]),
+                    Err(e) => {
+                        eprintln!("Error: Cannot add recipient: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+        }
+        Some("remove") => {
+            let [recipient] = options else {
+                eprintln!("Error: Give exactly one recipient to remove");
+                eprintln!("{}", usage);
+                std::process::exit(1);
+            };
+            if wallet.slots().iter().any(|slot| slot.kdf().is_some()) {
+                println!(
+                    "Removing a recipient moves the wallet to a new data key; every password and recovery slot is re-wrapped for it"
+                );
+            }
+
+            let opened = wallet.opened_slot();
+            let keyfile = wallet.keyfile().cloned();
+            let input_source = interactive_source(source);
+            let result = wallet.remove_recipient(recipient.trim(), |index, slot| {
+                if let Session::Password(password) = session
+                    && Some(index) == opened
+                {
+                    return Ok((password.clone(), keyfile.clone()));
+                }
+                if let SlotKind::Recovery { .. } = slot.kind {
+                    let prompt = format!("Recovery key or share for key slot {}: ", index);
+                    return Ok((read_recovery_key(&[], source, &prompt).passphrase(), None));
+                }
+                if slot.keyfile() && keyfile.is_none() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!(
+                            "Key slot {} requires its keyfile; pass --keyfile FILE",
+                            index
+                        ),
+                    ));
+                }
+                let prompt = format!("Password for key slot {} ({}): ", index, slot);
+                Ok((
+                    prompt::read_password(&prompt, &input_source)?,
+                    keyfile.clone(),
+                ))
+            });
+            match result {
+                Ok(()) => {
+                    println!("✓ Removed recipient {}", recipient.trim());
+                    println!("  The wallet is now encrypted under a new data key");
+                }
+                Err(e) => {
+                    eprintln!("Error: Cannot remove recipient: {}", e);
+                    eprintln!("  The wallet was not changed");
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ => {
+            eprintln!("Error: Unknown or incomplete recipients subcommand");
+            eprintln!("{}", usage);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Handles `wallet recovery`: stores a new recovery key in its own slot and
+/// prints it, or only its shares when it is split.
+fn create_recovery_key(wallet: &mut Wallet, options: &[String]) {
+    let mut split = None;
+    let mut export = None;
+    let mut iter = options.iter();
+    while let Some(option) = iter.next() {
+        let mut value = || {
+            iter.next().cloned().unwrap_or_else(|| {
+                eprintln!("Error: Option '{}' needs a value", option);
+                std::process::exit(1);
+            })
+        };
+        match option.as_str() {
+            "--split" => {
+                let value = value();
+                let parsed = value
+                    .split_once("-of-")
+                    .and_then(|(k, n)| Some((k.parse::<u8>().ok()?, n.parse::<u8>().ok()?)));
+                let Some((threshold, count)) = parsed else {
+                    eprintln!("Error: --split expects K-of-N, e.g. 3-of-5");
+                    std::process::exit(1);
+                };
+                split = Some((threshold, count));
+            }
+            "--export" => export = Some(PathBuf::from(value())),
+            _ => {
+                eprintln!("Error: Unknown option '{}' for recovery", option);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let recovery_key = RecoveryKey::generate().unwrap_or_else(|e| {
+        eprintln!("Error: Cannot generate recovery key: {}", e);
+        std::process::exit(1);
+    });
+    let shares = match split {
+        Some((threshold, count)) => recovery_key.split(threshold, count).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }),
+        None => Vec::new(),
+    };
+    let index = wallet.set_recovery_key(&recovery_key).unwrap_or_else(|e| {
+        eprintln!("Error: Cannot store recovery key: {}", e);
+        std::process::exit(1);
+    });
+    println!(
+        "✓ Recovery key stored in key slot {}; any earlier recovery key no longer works",
+        index
+    );
+
+    let printed: Vec<(String, SecretString)> = if shares.is_empty() {
+        vec![("recovery-key".to_string(), recovery_key.encode())]
+    } else {
+        println!(
+            "  Split into {} shares; any {} of them recover the wallet with 'wallet recover'",
+            shares.len(),
+            shares[0].threshold()
+        );
+        shares
+            .iter()
+            .map(|share| (format!("recovery-share-{}", share.index()), share.encode()))
+            .collect()
+    };
+    match export {
+        Some(dir) => {
+            for (name, text) in &printed {
+                let path = dir.join(format!("{}.txt", name));
+                if let Err(e) = recovery::export(&path, text) {
+                    eprintln!("Error: Cannot write '{}': {}", path.display(), e);
+                    std::process::exit(1);
+                }
+                println!("  Wrote {}", path.display());
+            }
+        }
+        None => {
+            println!(
+                "  Write it down and keep it offline; it opens the wallet without the password:"
+            );
+            for (name, text) in &printed {
+                println!();
+                println!("  {}:", name);
+                println!("      {}", &**text);
+            }
+        }
+    }
+}
+
+/// Handles `wallet recover [FILE...]`: rebuilds the recovery key from the
+/// key itself or enough shares, read from files or typed in, and sets a new
+/// master password.
+fn recover_wallet(wallet: &mut Wallet, args: &[String], source: &Option<PasswordSource>) {
+    let mut files = args.to_vec();
+    let force = take_arg(&mut files, "--force");
+    let key = read_recovery_key(&files, source, "Recovery key or share: ");
+
+    if let Err(e) = wallet.try_load(&key.passphrase()) {
+        match e.kind() {
+            std::io::ErrorKind::InvalidInput => {
+                eprintln!("Error: The recovery key does not open this wallet")
+            }
+            _ => eprintln!("Error: {}", e),
+        }
+        std::process::exit(1);
+    }
+    let new_password = read_new_password(source, force);
+    match wallet.reset_password(&new_password) {
+        Ok(removed) => {
+            println!(
+                "✓ Master password reset; {} old password slot(s) removed",
+                removed
+            );
+            println!(
+                "  The recovery key still works; run 'wallet recovery' to replace it if it was exposed"
+            );
+        }
+        Err(e) => {
+            eprintln!("Error: Failed to reset password: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Reads the recovery key from `files`, or asks for the key or enough of its
+/// shares with `prompt`, exiting if they are invalid.
+fn read_recovery_key(
+    files: &[String],
+    source: &Option<PasswordSource>,
+    prompt: &str,
+) -> RecoveryKey {
+    let parse = |text: &str| {
+        recovery::Recovery::parse(text).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        })
+    };
+
+    let mut inputs: Vec<recovery::Recovery> = files
+        .iter()
+        .map(|file| {
+            let text = std::fs::read(file)
+                .ok()
+                .and_then(|bytes| SecretBytes::new(bytes).into_string())
+                .unwrap_or_else(|| {
+                    eprintln!("Error: Cannot read recovery file '{}'", file);
+                    std::process::exit(1);
+                });
+            parse(&text)
+        })
+        .collect();
+    if inputs.is_empty() {
+        let input_source = interactive_source(source);
+        loop {
+            let text = prompt::read_password(prompt, &input_source).unwrap_or_else(|e| {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            });
+            let input = parse(&text);
+            let done = match &input {
+                recovery::Recovery::Key(_) => true,
+                recovery::Recovery::Share(share) => inputs.len() + 1 >= share.threshold() as usize,
+            };
+            inputs.push(input);
+            if done {
+                break;
+            }
+        }
+    }
+
+    let key = inputs.iter().find_map(|input| match input {
+        recovery::Recovery::Key(key) => Some(key.clone()),
+        recovery::Recovery::Share(_) => None,
+    });
+    let key = key.map(Ok).unwrap_or_else(|| {
+        let shares: Vec<recovery::Share> = inputs
+            .into_iter()
+            .filter_map(|input| match input {
+                recovery::Recovery::Share(share) => Some(share),
+                recovery::Recovery::Key(_) => None,
+            })
+            .collect();
+        RecoveryKey::combine(&shares)
+    });
+    key.unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Where to ask for secrets beyond the unlock password: more lines of
+/// stdin with `--password-stdin`, and the terminal otherwise.
+fn interactive_source(source: &Option<PasswordSource>) -> PasswordSource {
+    match source {
+        Some(PasswordSource::Stdin) => PasswordSource::Stdin,
+        _ => PasswordSource::Tty,
+    }
+}
+
+/// Handles `wallet keyfile new <FILE>`.
+fn new_keyfile(args: &[String]) {
+    let [subcommand, path] = args else {
+        eprintln!("Usage: wallet keyfile new <FILE>");
+        std::process::exit(1);
+    };
+    if subcommand != "new" {
+        eprintln!("Error: Unknown keyfile subcommand '{}'", subcommand);
+        eprintln!("Usage: wallet keyfile new <FILE>");
+        std::process::exit(1);
+    }
+    match keyfile::generate(path) {
+        Ok(()) => {
+            println!("✓ Wrote keyfile {}", path);
+            println!(
+                "  Bind a wallet with 'wallet --keyfile {} init' or 'wallet passwd --new-keyfile {}'",
+                path, path
+            );
+            println!("  Keep a backup: without it the wallet cannot be opened");
+        }
+        Err(e) => {
+            eprintln!("Error: Cannot create keyfile '{}': {}", path, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Handles `wallet identity new <FILE>`.
+fn new_identity(args: &[String]) {
+    let [subcommand, path] = args else {
+        eprintln!("Usage: wallet identity new <FILE>");
+        std::process::exit(1);
+    };
+    if subcommand != "new" {
+        eprintln!("Error: Unknown identity subcommand '{}'", subcommand);
+        eprintln!("Usage: wallet identity new <FILE>");
+        std::process::exit(1);
+    }
+    match recipient::generate_identity(path) {
+        Ok(public_key) => {
+            println!("✓ Wrote identity {}", path);
+            println!("  Public key: {}", public_key);
+            println!("  Share the public key so a wallet owner can run 'wallet recipients add'");
+            println!(
+                "  Then open the wallet with 'wallet --identity {} ...'",
+                path
+            );
+        }
+        Err(e) => {
+            eprintln!("Error: Cannot create identity '{}': {}", path, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Handles `wallet doctor`, exiting with [`EXIT_FINDINGS`] when a hardening
+/// step did not take effect.
+fn run_doctor(wallet: &Wallet) {
+    let wallet_len = std::fs::metadata(wallet.file_path()).map_or(0, |meta| meta.len());
+    let checks = harden::checks(wallet_len as usize);
+    for check in &checks {
+        let mark = if check.ok { "✓" } else { "✗" };
+        println!("{} {}: {}", mark, check.name, check.detail);
+    }
+    if checks.iter().any(|check| !check.ok) {
+        std::process::exit(EXIT_FINDINGS);
+    }
+}
+
+/// Handles `wallet audit`, exiting with [`EXIT_FINDINGS`] when there are
+/// more findings than `--fail-above` allows.
+fn run_audit(wallet: &Wallet, options: &[String]) {
+    let mut audit_options = AuditOptions::default();
+    let mut json = false;
+    let mut fail_above = None;
+
+    let mut iter = options.iter();
+    while let Some(option) = iter.next() {
+        let mut number = |option: &str| -> u64 {
+            match iter.next().map(|value| value.parse()) {
+                Some(Ok(value)) => value,
+                _ => {
+                    eprintln!("Error: Option '{}' needs a numeric value", option);
+                    std::process::exit(1);
+                }
+            }
+        };
+        match option.as_str() {
+            "--max-age" => audit_options.max_age_days = number(option),
+            "--min-score" => audit_options.min_score = number(option).min(4) as u8,
+            "--fail-above" => fail_above = Some(number(option) as usize),
+            "--json" => json = true,
+            _ => {
+                eprintln!("Error: Unknown option '{}' for audit", option);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let report = wallet.audit(&audit_options);
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report).expect("Failed to serialize audit report")
+        );
+    } else if report.findings.is_empty() {
+        println!("✓ No problems found in {} entries", report.entries_checked);
+    } else {
+        println!(
+            "Found {} problem(s) in {} entries:",
+            report.findings.len(),
+            report.entries_checked
+        );
+        for finding in &report.findings {
+            println!("  • {}", finding);
+        }
+    }
+
+    if let Some(limit) = fail_above
+        && report.findings.len() > limit
+    {
+        std::process::exit(EXIT_FINDINGS);
+    }
+}
+
+/// Warns when `value` appears in the breach database named by
+/// [`breach::DB_ENV`]. Does nothing when the variable is not set.
+fn warn_if_breached(key: &str, value: &str) {
+    let Some(path) = std::env::var_os(breach::DB_ENV) else {
+        return;
+    };
+    match breach::BreachDb::open(path).and_then(|mut db| db.lookup(value)) {
+        Ok(Some(count)) => eprintln!(
+            "Warning: The value for '{}' appears {} times in known data breaches",
+            key, count
+        ),
+        Ok(None) => {}
+        Err(e) => eprintln!("Warning: Breach check skipped: {}", e),
+    }
+}
+
+/// Handles `wallet breach-check --build-index DUMP INDEX`.
+fn build_breach_index(args: &[String]) {
+    let [dump, index] = args else {
+        eprintln!("Error: Usage: wallet breach-check --build-index DUMP INDEX");
+        std::process::exit(1);
+    };
+    match breach::build_index(dump, index) {
+        Ok(count) => println!("✓ Wrote {} hashes to {}", count, index),
+        Err(e) => {
+            eprintln!("Error: Cannot build breach index: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Handles `wallet breach-check`, exiting with [`EXIT_FINDINGS`] when any
+/// secret appears in the breach database.
+fn run_breach_check(wallet: &Wallet, options: &[String]) {
+    let mut db_path = std::env::var_os(breach::DB_ENV).map(PathBuf::from);
+    let mut json = false;
+
+    let mut iter = options.iter();
+    while let Some(option) = iter.next() {
+        match option.as_str() {
+            "--db" => match iter.next() {
+                Some(path) => db_path = Some(PathBuf::from(path)),
+                None => {
+                    eprintln!("Error: Option '--db' needs a file");
+                    std::process::exit(1);
+                }
+            },
+            "--json" => json = true,
+            _ => {
+                eprintln!("Error: Unknown option '{}' for breach-check", option);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let Some(db_path) = db_path else {
+        eprintln!(
+            "Error: No breach database given; use --db FILE or set {}",
+            breach::DB_ENV
+        );
+        std::process::exit(1);
+    };
+    let breaches =
+        match breach::BreachDb::open(&db_path).and_then(|mut db| wallet.breach_check(&mut db)) {
+            Ok(breaches) => breaches,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        };
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&breaches).expect("Failed to serialize breach report")
+        );
+    } else if breaches.is_empty() {
+        println!(
+            "✓ None of {} secrets appear in known breaches",
+            wallet.entries().count()
+        );
+    } else {
+        println!("Found {} breached secret(s):", breaches.len());
+        for breach in &breaches {
+            println!("  • {} (seen {} times)", breach.entry, breach.count);
+        }
+    }
+
+    if !breaches.is_empty() {
+        std::process::exit(EXIT_FINDINGS);
+    }
+}
+
+/// Handles `wallet otp <KEY> [--copy]`. The code goes to stdout and the time
+/// left to stderr so the output can be piped.
+fn show_otp(wallet: &mut Wallet, args: &[String]) {
+    let mut args = args.to_vec();
+    let copy = take_arg(&mut args, "--copy");
+    let [key] = args.as_slice() else {
+        eprintln!("Error: Usage: wallet otp <KEY> [--copy]");
+        std::process::exit(1);
+    };
+    let Some(entry) = wallet.entry_mut(key) else {
+        eprintln!("Error: Key '{}' not found in wallet", key);
+        std::process::exit(1);
+    };
+
+    let code = match entry.next_otp(entry::now()) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: Cannot compute a code for '{}': {}", key, e);
+            std::process::exit(1);
+        }
+    };
+    entry.mark_used();
+
+    if copy {
+        match clipboard::Clipboard::copy(&code.code) {
+            Ok(()) => println!("✓ Copied code for '{}' to clipboard", key),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        println!("{}", code.code);
+    }
+    if let Some(remaining) = code.remaining {
+        eprintln!("Valid for {}s", remaining);
+    }
+}
+
+/// Handles `wallet import-otp`. Accounts whose entry name is already taken
+/// are skipped so existing entries are never overwritten.
+fn import_otp(wallet: &mut Wallet, sources: &[String]) {
+    if sources.is_empty() {
+        eprintln!("Error: Usage: wallet import-otp <URI|FILE|IMAGE.png|->...");
+        std::process::exit(1);
+    }
+
+    let mut secrets = Vec::new();
+    for source in sources {
+        let text = if source == "-" {
+            prompt::read_secret_stdin()
+        } else {
+            import::read_source(source)
+        };
+        let parsed = text
+            .map_err(|e| e.to_string())
+            .and_then(|text| import::parse_uris(&text).map_err(|e| e.to_string()));
+        match parsed {
+            Ok(parsed) => secrets.extend(parsed),
+            Err(e) => {
+                eprintln!("Error: Cannot import from '{}': {}", source, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let mut imported = 0;
+    for secret in secrets {
+        let name = import::entry_name(&secret);
+        if wallet.key_exists(&name) {
+            eprintln!(
+                "Warning: Skipped '{}': an entry with that name exists",
+                name
+            );
+            continue;
+        }
+        wallet.insert_entry(import::to_entry(&secret));
+        println!("✓ Imported '{}'", name);
+        imported += 1;
+    }
+    println!("Imported {} account(s)", imported);
+}
+
+/// Handles `wallet agent [--timeout SECS] [--socket PATH] [--foreground]`.
+///
+/// Without `--foreground` the agent is started in its own session and the
+/// shell commands to use it are printed, like `eval $(ssh-agent)`.
+#[cfg(unix)]
+fn run_agent(options: &[String]) {
+    let mut socket = agent::default_socket_path();
+    let mut timeout = agent::DEFAULT_IDLE_TIMEOUT.as_secs();
+    let mut foreground = false;
+
+    let mut iter = options.iter();
+    while let Some(option) = iter.next() {
+        match (option.as_str(), iter.clone().next()) {
+            ("--foreground", _) => foreground = true,
+            ("--socket", Some(path)) => {
+                socket = PathBuf::from(path);
+                iter.next();
+            }
+            ("--timeout", Some(value)) if value.parse::<u64>().is_ok_and(|secs| secs > 0) => {
+                timeout = value.parse().unwrap();
+                iter.next();
+            }
+            _ => {
+                eprintln!("Error: Invalid option '{}' for agent", option);
+                eprintln!("Usage: wallet agent [--timeout SECS] [--socket PATH] [--foreground]");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let export = format!(
+        "{}={}; export {};",
+        agent::SOCK_ENV,
+        socket.display(),
+        agent::SOCK_ENV
+    );
+    if foreground {
+        let agent = match agent::Agent::bind(&socket, std::time::Duration::from_secs(timeout)) {
+            Ok(agent) => agent,
+            Err(e) => {
+                eprintln!("Error: Cannot start agent: {}", e);
+                std::process::exit(1);
+            }
+        };
+        println!("{}", export);
+        if let Err(e) = agent.run() {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    let exe = std::env::current_exe().expect("Cannot locate the wallet binary");
+    let mut daemon = Command::new(exe);
+    daemon
+        .args(["agent", "--foreground", "--timeout", &timeout.to_string()])
+        .arg("--socket")
+        .arg(&socket)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // Detach from the terminal so closing it does not stop the agent.
+    unsafe {
+        daemon.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+    let child = match daemon.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("Error: Cannot start agent: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let started = (0..50).any(|_| {
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        std::os::unix::net::UnixStream::connect(&socket).is_ok()
+    });
+    if !started {
+        eprintln!("Error: Agent did not start; run 'wallet agent --foreground' to see why");
+        std::process::exit(1);
+    }
+    println!("{}", export);
+    println!("echo Agent pid {};", child.id());
+}
+
+/// Handles `wallet lock`.
+#[cfg(unix)]
+fn lock_agent() {
+    let Some(client) = agent::Client::from_env() else {
+        eprintln!("Error: {} is not set; no agent to lock", agent::SOCK_ENV);
+        std::process::exit(1);
+    };
+    match client.lock() {
+        Ok(()) => println!("✓ Agent locked"),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
 }
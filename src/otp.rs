@@ -0,0 +1,455 @@
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use std::{fmt, str::FromStr};
+
+use crate::secret::SecretBytes;
+
+/// Custom entry field older versions kept the `otpauth://` URI or base32
+/// seed in; it is moved to [`crate::Entry::otp`] when the wallet is loaded.
+pub const OTP_FIELD: &str = "otp";
+
+/// Default TOTP time step in seconds.
+pub const DEFAULT_PERIOD: u64 = 30;
+
+/// Default number of digits in a code.
+pub const DEFAULT_DIGITS: u32 = 6;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OtpError {
+    Missing,
+    InvalidUri(String),
+    InvalidMigration(String),
+    InvalidSecret,
+    UnsupportedAlgorithm(String),
+    InvalidDigits(u32),
+    InvalidPeriod,
+    /// The HOTP counter is at its maximum and cannot be advanced.
+    CounterExhausted,
+}
+
+impl fmt::Display for OtpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OtpError::Missing => write!(
+                f,
+                "No otpauth URI is stored for this entry; add one with --otp"
+            ),
+            OtpError::InvalidUri(reason) => write!(f, "Invalid otpauth URI: {}", reason),
+            OtpError::InvalidMigration(reason) => {
+                write!(f, "Invalid otpauth-migration URI: {}", reason)
+            }
+            OtpError::InvalidSecret => write!(f, "The OTP secret is not valid base32"),
+            OtpError::UnsupportedAlgorithm(name) => {
+                write!(f, "Unsupported OTP algorithm '{}'", name)
+            }
+            OtpError::InvalidDigits(digits) => {
+                write!(f, "Codes must have 6 to 8 digits, not {}", digits)
+            }
+            OtpError::InvalidPeriod => write!(f, "The TOTP period must be at least one second"),
+            OtpError::CounterExhausted => {
+                write!(f, "The HOTP counter cannot be advanced any further")
+            }
+        }
+    }
+}
+
+impl std::error::Error for OtpError {}
+
+/// HMAC hash used to compute codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Algorithm {
+    #[default]
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl FromStr for Algorithm {
+    type Err = OtpError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "SHA1" => Ok(Algorithm::Sha1),
+            "SHA256" => Ok(Algorithm::Sha256),
+            "SHA512" => Ok(Algorithm::Sha512),
+            _ => Err(OtpError::UnsupportedAlgorithm(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Algorithm::Sha1 => write!(f, "SHA1"),
+            Algorithm::Sha256 => write!(f, "SHA256"),
+            Algorithm::Sha512 => write!(f, "SHA512"),
+        }
+    }
+}
+
+/// Whether codes are time based (RFC 6238) or counter based (RFC 4226).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtpKind {
+    Totp {
+        period: u64,
+    },
+    /// `counter` is the value the next code is computed from.
+    Hotp {
+        counter: u64,
+    },
+}
+
+/// A one-time password generator as described by an `otpauth://` URI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OtpSecret {
+    pub secret: SecretBytes,
+    pub algorithm: Algorithm,
+    pub digits: u32,
+    pub kind: OtpKind,
+    pub issuer: Option<String>,
+    pub account: Option<String>,
+}
+
+/// A computed code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Code {
+    pub code: String,
+    /// Seconds until a TOTP code expires; `None` for HOTP.
+    pub remaining: Option<u64>,
+}
+
+impl OtpSecret {
+    /// Parses an `otpauth://totp/...` or `otpauth://hotp/...` URI, or a bare
+    /// base32 seed which is treated as a default 6-digit, 30-second TOTP.
+    pub fn parse(value: &str) -> Result<Self, OtpError> {
+        let value = value.trim();
+        if has_scheme(value, "otpauth://") {
+            Self::parse_uri(value)
+        } else {
+            Ok(OtpSecret {
+                secret: base32_decode(value)?.into(),
+                algorithm: Algorithm::default(),
+                digits: DEFAULT_DIGITS,
+                kind: OtpKind::Totp {
+                    period: DEFAULT_PERIOD,
+                },
+                issuer: None,
+                account: None,
+            })
+        }
+    }
+
+    fn parse_uri(uri: &str) -> Result<Self, OtpError> {
+        let invalid = |reason: &str| OtpError::InvalidUri(reason.to_string());
+        let rest = &uri["otpauth://".len()..];
+        let (kind, rest) = rest
+            .split_once('/')
+            .ok_or_else(|| invalid("missing label"))?;
+        let (label, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+        let label = percent_decode(label)?;
+        let (mut issuer, account) = match label.split_once(':') {
+            Some((issuer, account)) => (Some(issuer.trim().to_string()), account.trim()),
+            None => (None, label.trim()),
+        };
+
+        let mut secret = None;
+        let mut algorithm = Algorithm::default();
+        let mut digits = DEFAULT_DIGITS;
+        let mut period = DEFAULT_PERIOD;
+        let mut counter = None;
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = percent_decode(value)?;
+            let number = |value: &str| {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| OtpError::InvalidUri(format!("{} must be a number", name)))
+            };
+            match name {
+                "secret" => secret = Some(base32_decode(&value)?.into()),
+                "algorithm" => algorithm = value.parse()?,
+                "digits" => digits = number(&value)?.try_into().unwrap_or(u32::MAX),
+                "period" => period = number(&value)?,
+                "counter" => counter = Some(number(&value)?),
+                "issuer" => issuer = Some(value),
+                // Unknown parameters such as `image` are ignored.
+                _ => {}
+            }
+        }
+
+        let kind = match kind.to_ascii_lowercase().as_str() {
+            "totp" => OtpKind::Totp { period },
+            "hotp" => OtpKind::Hotp {
+                counter: counter.ok_or_else(|| invalid("hotp needs a counter"))?,
+            },
+            other => return Err(OtpError::InvalidUri(format!("unknown type '{}'", other))),
+        };
+        let otp = OtpSecret {
+            secret: secret.ok_or_else(|| invalid("missing secret"))?,
+            algorithm,
+            digits,
+            kind,
+            issuer: issuer.filter(|issuer| !issuer.is_empty()),
+            account: Some(account.to_string()).filter(|account| !account.is_empty()),
+        };
+        otp.validate()?;
+        Ok(otp)
+    }
+
+    fn validate(&self) -> Result<(), OtpError> {
+        if !(6..=8).contains(&self.digits) {
+            return Err(OtpError::InvalidDigits(self.digits));
+        }
+        if self.kind == (OtpKind::Totp { period: 0 }) {
+            return Err(OtpError::InvalidPeriod);
+        }
+        Ok(())
+    }
+
+    /// Formats the generator as an `otpauth://` URI.
+    pub fn to_uri(&self) -> String {
+        let kind = match self.kind {
+            OtpKind::Totp { .. } => "totp",
+            OtpKind::Hotp { .. } => "hotp",
+        };
+        let label = match (&self.issuer, &self.account) {
+            (Some(issuer), Some(account)) => {
+                format!("{}:{}", percent_encode(issuer), percent_encode(account))
+            }
+            (Some(name), None) | (None, Some(name)) => percent_encode(name),
+            (None, None) => String::new(),
+        };
+        let mut uri = format!(
+            "otpauth://{}/{}?secret={}&algorithm={}&digits={}",
+            kind,
+            label,
+            base32_encode(&self.secret),
+            self.algorithm,
+            self.digits
+        );
+        match self.kind {
+            OtpKind::Totp { period } => uri.push_str(&format!("&period={}", period)),
+            OtpKind::Hotp { counter } => uri.push_str(&format!("&counter={}", counter)),
+        }
+        if let Some(issuer) = &self.issuer {
+            uri.push_str(&format!("&issuer={}", percent_encode(issuer)));
+        }
+        uri
+    }
+
+    /// Computes the current code. For HOTP the counter is advanced so the
+    /// same code is never produced twice; callers must persist the change.
+    pub fn next_code(&mut self, now: u64) -> Result<Code, OtpError> {
+        match &mut self.kind {
+            OtpKind::Totp { period } => Ok(Code {
+                code: totp(&self.secret, now, *period, self.digits, self.algorithm),
+                remaining: Some(*period - now % *period),
+            }),
+            OtpKind::Hotp { counter } => {
+                let next = counter.checked_add(1).ok_or(OtpError::CounterExhausted)?;
+                let code = hotp(&self.secret, *counter, self.digits, self.algorithm);
+                *counter = next;
+                Ok(Code {
+                    code,
+                    remaining: None,
+                })
+            }
+        }
+    }
+}
+
+/// Whether `value` starts with `scheme`, ignoring ASCII case as URI schemes do.
+pub fn has_scheme(value: &str, scheme: &str) -> bool {
+    value
+        .get(..scheme.len())
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(scheme))
+}
+
+/// Computes an RFC 4226 HOTP code.
+pub fn hotp(secret: &[u8], counter: u64, digits: u32, algorithm: Algorithm) -> String {
+    let message = counter.to_be_bytes();
+    let digest = match algorithm {
+        Algorithm::Sha1 => hmac::<Hmac<Sha1>>(secret, &message),
+        Algorithm::Sha256 => hmac::<Hmac<Sha256>>(secret, &message),
+        Algorithm::Sha512 => hmac::<Hmac<Sha512>>(secret, &message),
+    };
+
+    // Dynamic truncation, RFC 4226 section 5.3.
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes(digest[offset..offset + 4].try_into().unwrap()) & 0x7fff_ffff;
+    let code = binary as u64 % 10u64.pow(digits);
+    format!("{:0width$}", code, width = digits as usize)
+}
+
+/// Computes an RFC 6238 TOTP code for Unix time `now`.
+pub fn totp(secret: &[u8], now: u64, period: u64, digits: u32, algorithm: Algorithm) -> String {
+    hotp(secret, now / period, digits, algorithm)
+}
+
+fn hmac<M: Mac + hmac::digest::KeyInit>(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = <M as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Decodes RFC 4648 base32, ignoring case, spaces, dashes and padding.
+pub fn base32_decode(value: &str) -> Result<Vec<u8>, OtpError> {
+    let mut bytes = Vec::new();
+    let (mut buffer, mut bits) = (0u64, 0);
+    for c in value.chars().filter(|c| !matches!(c, ' ' | '-' | '=')) {
+        let index = BASE32_ALPHABET
+            .iter()
+            .position(|&a| a as char == c.to_ascii_uppercase())
+            .ok_or(OtpError::InvalidSecret)?;
+        buffer = (buffer << 5) | index as u64;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    if bytes.is_empty() {
+        return Err(OtpError::InvalidSecret);
+    }
+    Ok(bytes)
+}
+
+/// Encodes bytes as unpadded RFC 4648 base32.
+pub fn base32_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    let (mut buffer, mut bits) = (0u64, 0);
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u64;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    encoded
+}
+
+pub(crate) fn percent_decode(value: &str) -> Result<String, OtpError> {
+    let invalid = || OtpError::InvalidUri("bad percent-encoding".to_string());
+    let mut bytes = Vec::new();
+    let mut iter = value.bytes();
+    while let Some(byte) = iter.next() {
+        match byte {
+            b'%' => {
+                let hex = [
+                    iter.next().ok_or_else(invalid)?,
+                    iter.next().ok_or_else(invalid)?,
+                ];
+                let hex = std::str::from_utf8(&hex).map_err(|_| invalid())?;
+                bytes.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+            }
+            b'+' => bytes.push(b' '),
+            _ => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| invalid())
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED_SHA1: &[u8] = b"12345678901234567890";
+    const SEED_SHA256: &[u8] = b"12345678901234567890123456789012";
+    const SEED_SHA512: &[u8] = b"1234567890123456789012345678901234567890123456789012345678901234";
+
+    #[test]
+    fn test_hotp_rfc4226_vectors() {
+        let expected = [
+            "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583",
+            "399871", "520489",
+        ];
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(hotp(SEED_SHA1, counter as u64, 6, Algorithm::Sha1), *code);
+        }
+    }
+
+    #[test]
+    fn test_totp_rfc6238_vectors() {
+        let cases = [
+            (59, "94287082", "46119246", "90693936"),
+            (1111111109, "07081804", "68084774", "25091201"),
+            (20000000000, "65353130", "77737706", "47863826"),
+        ];
+        for (time, sha1, sha256, sha512) in cases {
+            assert_eq!(totp(SEED_SHA1, time, 30, 8, Algorithm::Sha1), sha1);
+            assert_eq!(totp(SEED_SHA256, time, 30, 8, Algorithm::Sha256), sha256);
+            assert_eq!(totp(SEED_SHA512, time, 30, 8, Algorithm::Sha512), sha512);
+        }
+    }
+
+    #[test]
+    fn test_base32_round_trip() {
+        assert_eq!(base32_encode(SEED_SHA1), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(
+            base32_decode("gezd gnbv-gy3t qojq gezd gnbv gy3t qojq====").unwrap(),
+            SEED_SHA1
+        );
+        assert_eq!(base32_decode("not base32!"), Err(OtpError::InvalidSecret));
+    }
+
+    #[test]
+    fn test_parse_uri_and_round_trip() {
+        let otp = OtpSecret::parse(
+            "otpauth://totp/ACME%20Co:john@example.com?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ\
+             &issuer=ACME%20Co&algorithm=SHA256&digits=8&period=60&image=x",
+        )
+        .unwrap();
+        assert_eq!(otp.algorithm, Algorithm::Sha256);
+        assert_eq!(otp.digits, 8);
+        assert_eq!(otp.kind, OtpKind::Totp { period: 60 });
+        assert_eq!(otp.issuer.as_deref(), Some("ACME Co"));
+        assert_eq!(otp.account.as_deref(), Some("john@example.com"));
+        assert_eq!(OtpSecret::parse(&otp.to_uri()).unwrap(), otp);
+
+        assert!(matches!(
+            OtpSecret::parse("otpauth://totp/x?secret=GEZDGNBV&digits=9"),
+            Err(OtpError::InvalidDigits(9))
+        ));
+        assert!(matches!(
+            OtpSecret::parse("otpauth://hotp/x?secret=GEZDGNBV"),
+            Err(OtpError::InvalidUri(_))
+        ));
+    }
+
+    #[test]
+    fn test_next_code_advances_hotp_counter() {
+        let mut otp =
+            OtpSecret::parse("otpauth://hotp/x?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&counter=1")
+                .unwrap();
+        assert_eq!(otp.next_code(0).unwrap().code, "287082");
+        assert_eq!(otp.next_code(0).unwrap().code, "359152");
+        assert_eq!(otp.kind, OtpKind::Hotp { counter: 3 });
+
+        otp.kind = OtpKind::Hotp { counter: u64::MAX };
+        assert_eq!(otp.next_code(0), Err(OtpError::CounterExhausted));
+        assert_eq!(otp.kind, OtpKind::Hotp { counter: u64::MAX });
+
+        let mut totp = OtpSecret::parse("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ").unwrap();
+        assert_eq!(totp.next_code(59).unwrap().remaining, Some(1));
+    }
+}
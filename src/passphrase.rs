@@ -0,0 +1,248 @@
+use rand::{Rng, TryRngCore, rngs::OsRng};
+use std::{fs, io, path::Path};
+use zeroize::Zeroize;
+
+use crate::{generator::GeneratorError, secret::SecretString};
+
+/// The bundled 2048-word English list from BIP-0039 (11 bits per word).
+///
+/// This is shorter than the 7776-word EFF long list (12.9 bits per word), so
+/// the default policy draws [`DEFAULT_WORDS`] words instead of the usual
+/// seven. Lists in the EFF diceware format (`11111<TAB>abacus`) can be
+/// loaded with [`Wordlist::from_file`].
+pub const BUNDLED_WORDLIST: &str = include_str!("wordlists/english.txt");
+
+/// A list of unique words to draw passphrase words from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wordlist {
+    words: Vec<String>,
+}
+
+impl Wordlist {
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED_WORDLIST).expect("bundled wordlist is valid")
+    }
+
+    /// Loads a wordlist file with one word per line. A leading dice roll
+    /// column, as in the EFF lists, is ignored.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut words: Vec<String> = Vec::new();
+        for line in text.lines() {
+            let mut fields = line.split_whitespace();
+            let word = match (fields.next(), fields.next()) {
+                (Some(dice), Some(word)) if dice.chars().all(|c| ('1'..='6').contains(&c)) => word,
+                (Some(word), None) => word,
+                (None, _) => continue,
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Unexpected wordlist line '{}'", line),
+                    ));
+                }
+            };
+            words.push(word.to_string());
+        }
+        words.sort();
+        words.dedup();
+
+        if words.len() < 2 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "A wordlist needs at least two distinct words",
+            ));
+        }
+        Ok(Wordlist { words })
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    pub fn words(&self) -> &[String] {
+        &self.words
+    }
+
+    /// Entropy contributed by one uniformly chosen word.
+    pub fn bits_per_word(&self) -> f64 {
+        (self.words.len() as f64).log2()
+    }
+}
+
+/// How passphrase words are capitalized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Capitalization {
+    #[default]
+    Lower,
+    Upper,
+    /// Every word starts with a capital letter.
+    Title,
+    /// One randomly chosen word is written in capitals.
+    RandomWord,
+}
+
+impl std::str::FromStr for Capitalization {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lower" => Ok(Capitalization::Lower),
+            "upper" => Ok(Capitalization::Upper),
+            "title" => Ok(Capitalization::Title),
+            "random" => Ok(Capitalization::RandomWord),
+            _ => Err(format!(
+                "Unknown capitalization '{}' (use lower, upper, title or random)",
+                s
+            )),
+        }
+    }
+}
+
+/// Default number of words: 99 bits from the bundled list, more than seven
+/// words from the EFF long list (90.5 bits) give.
+pub const DEFAULT_WORDS: usize = 9;
+
+/// Rules for generating a diceware-style passphrase.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassphrasePolicy {
+    pub words: usize,
+    pub separator: String,
+    pub capitalization: Capitalization,
+    /// Number of random digits appended to randomly chosen words.
+    pub digits: usize,
+}
+
+impl Default for PassphrasePolicy {
+    fn default() -> Self {
+        PassphrasePolicy {
+            words: DEFAULT_WORDS,
+            separator: "-".to_string(),
+            capitalization: Capitalization::Lower,
+            digits: 0,
+        }
+    }
+}
+
+/// A generated passphrase and its entropy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Passphrase {
+    pub phrase: SecretString,
+    /// Entropy in bits, assuming the attacker knows the wordlist and policy.
+    pub entropy_bits: f64,
+}
+
+/// Generates a passphrase from `wordlist` using the operating system RNG.
+pub fn generate(
+    policy: &PassphrasePolicy,
+    wordlist: &Wordlist,
+) -> Result<Passphrase, GeneratorError> {
+    if policy.words == 0 || wordlist.is_empty() {
+        return Err(GeneratorError::NoWords);
+    }
+
+    let mut rng = OsRng.unwrap_err();
+    let mut words: Vec<String> = (0..policy.words)
+        .map(|_| wordlist.words[rng.random_range(0..wordlist.len())].clone())
+        .collect();
+    let mut entropy_bits = policy.words as f64 * wordlist.bits_per_word();
+
+    match policy.capitalization {
+        Capitalization::Lower => {}
+        Capitalization::Upper => words.iter_mut().for_each(|w| rewrite(w, str::to_uppercase)),
+        Capitalization::Title => words.iter_mut().for_each(|w| rewrite(w, title_case)),
+        Capitalization::RandomWord => {
+            let index = rng.random_range(0..words.len());
+            rewrite(&mut words[index], str::to_uppercase);
+            entropy_bits += (words.len() as f64).log2();
+        }
+    }
+
+    for _ in 0..policy.digits {
+        let index = rng.random_range(0..words.len());
+        let digit = char::from(b'0' + rng.random_range(0..10u8));
+        rewrite(&mut words[index], |w| format!("{}{}", w, digit));
+        entropy_bits += 10f64.log2();
+    }
+
+    let phrase = SecretString::new(words.join(&policy.separator));
+    words.iter_mut().for_each(Zeroize::zeroize);
+    Ok(Passphrase {
+        phrase,
+        entropy_bits,
+    })
+}
+
+/// Replaces `word` with `f(word)`, wiping the old value first.
+fn rewrite(word: &mut String, f: impl Fn(&str) -> String) {
+    let new = f(word);
+    word.zeroize();
+    *word = new;
+}
+
+fn title_case(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_wordlist() {
+        let wordlist = Wordlist::bundled();
+        assert_eq!(wordlist.len(), 2048);
+        assert_eq!(wordlist.bits_per_word(), 11.0);
+    }
+
+    #[test]
+    fn test_default_policy_entropy() {
+        let passphrase = generate(&PassphrasePolicy::default(), &Wordlist::bundled()).unwrap();
+        assert_eq!(passphrase.phrase.split('-').count(), DEFAULT_WORDS);
+        assert!(passphrase.entropy_bits >= 90.0);
+    }
+
+    #[test]
+    fn test_parse_eff_format() {
+        let wordlist = Wordlist::parse("11111\tabacus\n11112\tabdomen\n\n11113\tabide\n").unwrap();
+        assert_eq!(wordlist.words, vec!["abacus", "abdomen", "abide"]);
+        assert!(Wordlist::parse("only\n").is_err());
+    }
+
+    #[test]
+    fn test_generate_follows_policy() {
+        let policy = PassphrasePolicy {
+            words: 5,
+            separator: ".".to_string(),
+            capitalization: Capitalization::Title,
+            digits: 1,
+        };
+        let passphrase = generate(&policy, &Wordlist::bundled()).unwrap();
+        let words: Vec<&str> = passphrase.phrase.split('.').collect();
+        assert_eq!(words.len(), 5);
+        assert!(
+            words
+                .iter()
+                .all(|w| w.starts_with(|c: char| c.is_uppercase()))
+        );
+        assert_eq!(
+            passphrase
+                .phrase
+                .chars()
+                .filter(|c| c.is_ascii_digit())
+                .count(),
+            1
+        );
+        assert!((passphrase.entropy_bits - (55.0 + 10f64.log2())).abs() < 1e-9);
+    }
+}
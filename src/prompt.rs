@@ -0,0 +1,279 @@
+use std::{
+    fs,
+    io::{self, BufRead, Read, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+use zeroize::Zeroize;
+
+use crate::secret::SecretString;
+
+/// Number of attempts allowed when unlocking a wallet interactively.
+pub const MAX_ATTEMPTS: u32 = 3;
+
+/// Environment variable consulted by [`PasswordSource::Env`].
+pub const PASSWORD_ENV: &str = "WALLET_PASSWORD";
+
+/// Where passwords are read from.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum PasswordSource {
+    /// The controlling terminal (`/dev/tty`) with echo disabled.
+    #[default]
+    Tty,
+    /// Plain lines from stdin. Only used when explicitly requested.
+    Stdin,
+    /// One line per read from an inherited file descriptor.
+    Fd(i32),
+    /// The first line of a file that only its owner can read.
+    File(PathBuf),
+    /// The [`PASSWORD_ENV`] environment variable.
+    Env,
+}
+
+impl PasswordSource {
+    /// Whether reading from this source may prompt a person.
+    pub fn is_interactive(&self) -> bool {
+        matches!(self, PasswordSource::Tty | PasswordSource::Stdin)
+    }
+}
+
+/// Prompts for a password without echoing it.
+///
+/// With [`PasswordSource::Tty`] the prompt is written to and the password
+/// read from the controlling terminal, even when stdin/stdout are redirected.
+/// Whatever the source, only the line break ending the password is removed;
+/// leading and trailing spaces are part of it.
+pub fn read_password(prompt: &str, source: &PasswordSource) -> io::Result<SecretString> {
+    let password = match source {
+        PasswordSource::Tty => rpassword::prompt_password(prompt).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "Cannot read password from terminal ({}); use --password-stdin to read it from stdin",
+                    e
+                ),
+            )
+        }),
+        PasswordSource::Stdin => {
+            eprint!("{}", prompt);
+            io::stderr().flush()?;
+            read_line(&mut io::stdin().lock())
+        }
+        PasswordSource::Fd(fd) => read_fd_line(*fd),
+        PasswordSource::File(path) => read_password_file(path),
+        PasswordSource::Env => std::env::var(PASSWORD_ENV).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not set", PASSWORD_ENV),
+            )
+        }),
+    }?;
+    Ok(SecretString::new(password))
+}
+
+/// Reads the first line of `path`, refusing files readable by group or others.
+fn read_password_file(path: &Path) -> io::Result<String> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = fs::metadata(path)?.permissions().mode();
+        if mode & 0o077 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "Password file {} is accessible by other users (mode {:o}); run chmod 600 on it",
+                    path.display(),
+                    mode & 0o777
+                ),
+            ));
+        }
+    }
+
+    let mut contents = fs::read_to_string(path)?;
+    let password = contents.lines().next().unwrap_or("").to_string();
+    contents.zeroize();
+    Ok(password)
+}
+
+/// Reads one line from file descriptor `fd` without consuming anything past
+/// the newline, so consecutive reads return consecutive lines.
+#[cfg(unix)]
+fn read_fd_line(fd: i32) -> io::Result<String> {
+    use std::mem::ManuallyDrop;
+    use std::os::unix::io::FromRawFd;
+
+    if fd < 0 || unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("File descriptor {} is not open", fd),
+        ));
+    }
+
+    // The descriptor is owned by whoever opened it; never close it here.
+    let mut file = ManuallyDrop::new(unsafe { fs::File::from_raw_fd(fd) });
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        match file.read(&mut byte)? {
+            0 if line.is_empty() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("No password given on file descriptor {}", fd),
+                ));
+            }
+            0 => break,
+            _ if byte[0] == b'\n' => break,
+            _ => line.push(byte[0]),
+        }
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    let password = std::str::from_utf8(&line)
+        .map(str::to_string)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Password is not valid UTF-8"));
+    line.zeroize();
+    password
+}
+
+#[cfg(not(unix))]
+fn read_fd_line(_fd: i32) -> io::Result<String> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "--password-fd is only supported on Unix",
+    ))
+}
+
+/// Reads a secret value from all of stdin.
+///
+/// Multi-line values are kept intact; only the final line break is removed.
+pub fn read_secret_stdin() -> io::Result<SecretString> {
+    let mut value = String::new();
+    io::stdin().lock().read_to_string(&mut value)?;
+    Ok(SecretString::new(strip_final_newline(value)))
+}
+
+fn strip_final_newline(mut value: String) -> String {
+    if value.ends_with('\n') {
+        value.pop();
+        if value.ends_with('\r') {
+            value.pop();
+        }
+    }
+    value
+}
+
+/// Reads one line, stripping only its line break. EOF is an error.
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "No password given on stdin",
+        ));
+    }
+    Ok(strip_final_newline(line))
+}
+
+/// Asks for a password until `unlock` accepts it, up to [`MAX_ATTEMPTS`] times.
+///
+/// Non-interactive sources get a single attempt. Otherwise only a wrong
+/// password ([`io::ErrorKind::InvalidInput`]) is retried, and the wait before
+/// the next prompt grows after each failure (1s, 2s, ...). Returns the
+/// accepted password, or the last error.
+pub fn unlock_with_retry<T, F>(
+    prompt: &str,
+    source: &PasswordSource,
+    mut unlock: F,
+) -> io::Result<(SecretString, T)>
+where
+    F: FnMut(&str) -> io::Result<T>,
+{
+    // Retrying only makes sense when a person can type a different password.
+    let max_attempts = if source.is_interactive() {
+        MAX_ATTEMPTS
+    } else {
+        1
+    };
+    let mut attempt = 1;
+    loop {
+        let password = read_password(prompt, source)?;
+        match unlock(&password) {
+            Ok(value) => return Ok((password, value)),
+            Err(e) if attempt >= max_attempts || e.kind() != io::ErrorKind::InvalidInput => {
+                return Err(e);
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                thread::sleep(retry_delay(attempt));
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Delay before the attempt following failed attempt number `attempt`.
+fn retry_delay(attempt: u32) -> Duration {
+    Duration::from_secs(attempt as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_line_keeps_spaces_and_rejects_eof() {
+        let mut input = io::Cursor::new(b"  hunter2 \r\n".to_vec());
+        assert_eq!(read_line(&mut input).unwrap(), "  hunter2 ");
+        assert_eq!(
+            read_line(&mut input).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_password_file_must_be_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("wallet-pwfile-{}", std::process::id()));
+        fs::write(&path, " hunter2 \r\nignored\n").unwrap();
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        assert_eq!(
+            read_password_file(&path).unwrap_err().kind(),
+            io::ErrorKind::PermissionDenied
+        );
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(read_password_file(&path).unwrap(), " hunter2 ");
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fd_must_be_open() {
+        for fd in [-1, 9999] {
+            let err = read_fd_line(fd).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn test_strip_final_newline_keeps_inner_lines() {
+        assert_eq!(
+            strip_final_newline("line one\nline two\r\n".to_string()),
+            "line one\nline two"
+        );
+        assert_eq!(strip_final_newline("no newline".to_string()), "no newline");
+    }
+
+    #[test]
+    fn test_retry_delay_increases() {
+        assert!(retry_delay(2) > retry_delay(1));
+    }
+}
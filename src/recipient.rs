@@ -0,0 +1,114 @@
+use age::secrecy::ExposeSecret;
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::secret::{SecretBytes, SecretString};
+
+pub use age::x25519::{Identity, Recipient};
+
+/// Environment variable naming the identity file, as an alternative to
+/// `--identity`.
+pub const IDENTITY_ENV: &str = "WALLET_IDENTITY";
+
+/// Parses an age X25519 public key (`age1...`).
+pub fn parse_recipient(text: &str) -> io::Result<Recipient> {
+    text.trim().parse().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid recipient '{}': {}", text, e),
+        )
+    })
+}
+
+/// Reads the identity (`AGE-SECRET-KEY-1...`) from an age identity file, as
+/// written by `age-keygen` or [`generate_identity`]. Blank lines and `#`
+/// comments are skipped; only the first key is used.
+pub fn read_identity(path: impl AsRef<Path>) -> io::Result<Identity> {
+    let path = path.as_ref();
+    let contents = SecretString::new(fs::read_to_string(path)?);
+    let line = contents
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Identity file {} holds no key", path.display()),
+            )
+        })?;
+    line.parse().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid identity in {}: {}", path.display(), e),
+        )
+    })
+}
+
+/// Writes a new identity to a file that only its owner can read, returning
+/// its public key. An existing file is never overwritten.
+pub fn generate_identity(path: impl AsRef<Path>) -> io::Result<Recipient> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+
+    let identity = Identity::generate();
+    let recipient = identity.to_public();
+    writeln!(file, "# public key: {}", recipient)?;
+    writeln!(file, "{}", identity.to_string().expose_secret())?;
+    file.sync_all()?;
+    Ok(recipient)
+}
+
+/// Encrypts `key` to `recipient` as an age file.
+pub(crate) fn wrap_key(recipient: &Recipient, key: &[u8]) -> Vec<u8> {
+    age::encrypt(recipient, key).expect("encryption failed")
+}
+
+/// Decrypts a key wrapped by [`wrap_key`], or `None` if `identity` is not
+/// its recipient.
+pub(crate) fn unwrap_key(identity: &Identity, wrapped: &[u8]) -> Option<SecretBytes> {
+    age::decrypt(identity, wrapped).ok().map(SecretBytes::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_and_read_identity() {
+        let path =
+            std::env::temp_dir().join(format!("wallet-identity-test-{}", std::process::id()));
+        let recipient = generate_identity(&path).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let identity = read_identity(&path).unwrap();
+        assert_eq!(identity.to_public().to_string(), recipient.to_string());
+        assert_eq!(
+            parse_recipient(&format!(" {}\n", recipient))
+                .unwrap()
+                .to_string(),
+            recipient.to_string()
+        );
+        assert!(parse_recipient("age1notakey").is_err());
+
+        let wrapped = wrap_key(&recipient, b"data key");
+        assert_eq!(unwrap_key(&identity, &wrapped).unwrap(), b"data key");
+        assert!(unwrap_key(&Identity::generate(), &wrapped).is_none());
+
+        assert_eq!(
+            generate_identity(&path).unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
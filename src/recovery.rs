@@ -0,0 +1,311 @@
+use rand::{TryRngCore, rngs::OsRng};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt,
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+};
+use zeroize::Zeroizing;
+
+use crate::secret::{SecretBytes, SecretString};
+use crate::shamir;
+
+/// Random bytes in a recovery key: 128 bits, beyond any guessing attack.
+pub const KEY_LEN: usize = 16;
+
+/// Bytes in a share: threshold, index and one value per key byte.
+const SHARE_LEN: usize = 2 + KEY_LEN;
+
+/// Bytes of SHA-256 appended to catch typos when a key or share is typed in.
+const CHECKSUM_LEN: usize = 2;
+
+/// Hex digits per dash-separated group in the printed form.
+const GROUP_LEN: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecoveryError {
+    /// The text is not a recovery key or share.
+    InvalidFormat(String),
+    /// The checksum does not match, usually because of a typo.
+    Checksum,
+    /// The share count or threshold is out of range.
+    InvalidSplit(String),
+    /// The shares belong to different splits or repeat one another.
+    MismatchedShares(String),
+    /// Fewer shares than the threshold were given.
+    NotEnoughShares { have: usize, need: usize },
+}
+
+impl fmt::Display for RecoveryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecoveryError::InvalidFormat(reason) => {
+                write!(f, "Not a recovery key or share: {}", reason)
+            }
+            RecoveryError::Checksum => write!(
+                f,
+                "Checksum does not match; check the recovery key or share for typos"
+            ),
+            RecoveryError::InvalidSplit(reason) => write!(f, "Invalid split: {}", reason),
+            RecoveryError::MismatchedShares(reason) => write!(f, "Shares do not match: {}", reason),
+            RecoveryError::NotEnoughShares { have, need } => {
+                write!(f, "{} of {} required shares given", have, need)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RecoveryError {}
+
+/// A high-entropy key that opens the wallet from its own key slot, for when
+/// the master password is lost.
+///
+/// Printed as dash-separated groups of hex digits ending in a checksum, e.g.
+/// `3f9a-0c1e-...-7b2d`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecoveryKey {
+    bytes: SecretBytes,
+}
+
+/// One of the `n` parts a recovery key was split into, any `threshold` of
+/// which recover the key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Share {
+    threshold: u8,
+    index: u8,
+    value: SecretBytes,
+}
+
+/// A recovery key or a share, as typed in by the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Recovery {
+    Key(RecoveryKey),
+    Share(Share),
+}
+
+impl RecoveryKey {
+    /// Generates a new random recovery key.
+    pub fn generate() -> io::Result<Self> {
+        let mut bytes = Zeroizing::new([0u8; KEY_LEN]);
+        OsRng
+            .try_fill_bytes(&mut bytes[..])
+            .map_err(io::Error::other)?;
+        Ok(RecoveryKey {
+            bytes: SecretBytes::new(bytes.to_vec()),
+        })
+    }
+
+    /// The printable form, with checksum.
+    pub fn encode(&self) -> SecretString {
+        encode(&self.bytes)
+    }
+
+    /// The secret the key slot is derived from, as hex without checksum.
+    pub fn passphrase(&self) -> SecretString {
+        SecretString::new(self.bytes.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+
+    /// Splits the key into `count` shares, any `threshold` of which recover it.
+    pub fn split(&self, threshold: u8, count: u8) -> Result<Vec<Share>, RecoveryError> {
+        if threshold < 2 || threshold > count {
+            return Err(RecoveryError::InvalidSplit(format!(
+                "need 2 <= threshold <= shares, got {} of {}",
+                threshold, count
+            )));
+        }
+        Ok(
+            shamir::split(&self.bytes, threshold, count, &mut OsRng.unwrap_err())
+                .into_iter()
+                .map(|(index, value)| Share {
+                    threshold,
+                    index,
+                    value,
+                })
+                .collect(),
+        )
+    }
+
+    /// Recovers the key from at least `threshold` shares of one split.
+    pub fn combine(shares: &[Share]) -> Result<Self, RecoveryError> {
+        let need = shares.first().map_or(2, |share| share.threshold as usize);
+        if shares.iter().any(|share| share.threshold as usize != need) {
+            return Err(RecoveryError::MismatchedShares(
+                "they have different thresholds".to_string(),
+            ));
+        }
+        for (i, share) in shares.iter().enumerate() {
+            if shares[..i].iter().any(|other| other.index == share.index) {
+                return Err(RecoveryError::MismatchedShares(format!(
+                    "share {} was given twice",
+                    share.index
+                )));
+            }
+        }
+        if shares.len() < need {
+            return Err(RecoveryError::NotEnoughShares {
+                have: shares.len(),
+                need,
+            });
+        }
+        let points: Vec<(u8, &[u8])> = shares
+            .iter()
+            .map(|share| (share.index, &share.value[..]))
+            .collect();
+        Ok(RecoveryKey {
+            bytes: shamir::combine(&points),
+        })
+    }
+}
+
+impl Share {
+    /// Number of shares needed to recover the key.
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// Position of the share in its split, starting at 1.
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// The printable form: threshold, index and value, with checksum.
+    pub fn encode(&self) -> SecretString {
+        let mut payload = Zeroizing::new(Vec::with_capacity(SHARE_LEN));
+        payload.extend([self.threshold, self.index]);
+        payload.extend_from_slice(&self.value);
+        encode(&payload)
+    }
+}
+
+impl Recovery {
+    /// Parses a printed recovery key or share. Dashes, whitespace and case
+    /// are ignored.
+    pub fn parse(text: &str) -> Result<Self, RecoveryError> {
+        let digits: SecretString = text
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '-')
+            .collect::<String>()
+            .to_ascii_lowercase()
+            .into();
+        if !digits.len().is_multiple_of(2) || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(RecoveryError::InvalidFormat(
+                "expected groups of hex digits".to_string(),
+            ));
+        }
+        let bytes = SecretBytes::new(
+            (0..digits.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+                .collect(),
+        );
+        if bytes.len() < CHECKSUM_LEN {
+            return Err(RecoveryError::InvalidFormat("too short".to_string()));
+        }
+        let (payload, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+
+        match payload.len() {
+            KEY_LEN | SHARE_LEN if checksum != &Sha256::digest(payload)[..CHECKSUM_LEN] => {
+                Err(RecoveryError::Checksum)
+            }
+            KEY_LEN => Ok(Recovery::Key(RecoveryKey {
+                bytes: SecretBytes::new(payload.to_vec()),
+            })),
+            SHARE_LEN if payload[0] < 2 || payload[1] == 0 => Err(RecoveryError::InvalidFormat(
+                "invalid share header".to_string(),
+            )),
+            SHARE_LEN => Ok(Recovery::Share(Share {
+                threshold: payload[0],
+                index: payload[1],
+                value: SecretBytes::new(payload[2..].to_vec()),
+            })),
+            _ => Err(RecoveryError::InvalidFormat(format!(
+                "{} hex digits is neither a key nor a share",
+                digits.len()
+            ))),
+        }
+    }
+}
+
+/// Writes a printed key or share to a new file that only its owner can
+/// read, e.g. to hand a share over on a USB stick. Existing files are never
+/// overwritten.
+pub fn export(path: impl AsRef<Path>, text: &str) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    writeln!(file, "{}", text)?;
+    file.sync_all()
+}
+
+/// Hex-encodes `payload` followed by its checksum in dash-separated groups.
+fn encode(payload: &[u8]) -> SecretString {
+    let checksum = Sha256::digest(payload);
+    let digits: SecretString = payload
+        .iter()
+        .chain(&checksum[..CHECKSUM_LEN])
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>()
+        .into();
+    let groups: Vec<&str> = (0..digits.len())
+        .step_by(GROUP_LEN)
+        .map(|i| &digits[i..(i + GROUP_LEN).min(digits.len())])
+        .collect();
+    groups.join("-").into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_round_trip_and_checksum() {
+        let key = RecoveryKey::generate().unwrap();
+        let printed = key.encode();
+        assert_eq!(printed.len(), 44);
+        assert_eq!(
+            Recovery::parse(&printed.to_uppercase()).unwrap(),
+            Recovery::Key(key.clone())
+        );
+        assert_eq!(key.passphrase().len(), 2 * KEY_LEN);
+
+        // Change one digit.
+        let mut typo = printed.to_string();
+        let last = if typo.ends_with('0') { "1" } else { "0" };
+        typo.replace_range(typo.len() - 1.., last);
+        assert_eq!(Recovery::parse(&typo), Err(RecoveryError::Checksum));
+        assert!(Recovery::parse("not-a-key").is_err());
+    }
+
+    #[test]
+    fn test_shares_recover_the_key() {
+        let key = RecoveryKey::generate().unwrap();
+        let shares: Vec<Share> = key
+            .split(2, 3)
+            .unwrap()
+            .iter()
+            .map(|share| match Recovery::parse(&share.encode()).unwrap() {
+                Recovery::Share(share) => share,
+                Recovery::Key(_) => panic!("share parsed as a key"),
+            })
+            .collect();
+        assert_eq!(shares[2].index(), 3);
+        assert_eq!(shares[2].threshold(), 2);
+
+        let two = [shares[2].clone(), shares[0].clone()];
+        assert_eq!(RecoveryKey::combine(&two).unwrap(), key);
+        assert_eq!(
+            RecoveryKey::combine(&shares[..1]),
+            Err(RecoveryError::NotEnoughShares { have: 1, need: 2 })
+        );
+        let repeated = [shares[1].clone(), shares[1].clone()];
+        assert!(matches!(
+            RecoveryKey::combine(&repeated),
+            Err(RecoveryError::MismatchedShares(_))
+        ));
+        assert!(key.split(1, 3).is_err());
+        assert!(key.split(4, 3).is_err());
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// Environment variable that enables the sandbox (`1`) without `--sandbox`.
+pub const SANDBOX_ENV: &str = "WALLET_SANDBOX";
+
+/// Directories the clipboard and QR helpers are executed and loaded from.
+const SYSTEM_DIRS: &[&str] = &["/usr", "/bin", "/lib", "/lib64", "/etc", "/nix/store"];
+
+/// Devices used for prompts, child process I/O and randomness.
+const DEVICES: &[&str] = &["/dev/tty", "/dev/null", "/dev/urandom"];
+
+#[derive(Debug)]
+pub enum SandboxError {
+    /// The kernel or platform cannot enforce the sandbox.
+    Unsupported(String),
+    /// The sandbox is supported but could not be set up.
+    Failed(String),
+}
+
+impl fmt::Display for SandboxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SandboxError::Unsupported(reason) => write!(
+                f,
+                "{}; run without --sandbox and unset {}",
+                reason, SANDBOX_ENV
+            ),
+            SandboxError::Failed(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SandboxError {}
+
+/// Whether the sandbox was asked for with `--sandbox` or [`SANDBOX_ENV`].
+pub fn requested(flag: bool) -> bool {
+    flag || env::var(SANDBOX_ENV).is_ok_and(|value| matches!(value.as_str(), "1" | "on" | "true"))
+}
+
+/// Restrictions applied to the running process by [`Sandbox::apply`].
+///
+/// Landlock limits the filesystem to the wallet's own directory
+/// (read-write), which must hold nothing but the wallet, and the config
+/// directory, system directories and any files the command was given
+/// (read-only). A seccomp filter makes every `socket(2)` call other
+/// than `AF_UNIX` fail, so the process cannot reach the network while the
+/// agent and X11 clipboard still work. Both are inherited by child processes.
+#[derive(Debug, Clone, Default)]
+pub struct Sandbox {
+    read_write: Vec<PathBuf>,
+    read_only: Vec<PathBuf>,
+}
+
+impl Sandbox {
+    /// A sandbox for the wallet stored at `wallet_path`.
+    ///
+    /// Saving replaces the wallet by renaming a temporary file over it,
+    /// which Landlock can only allow for a whole directory tree. So the
+    /// wallet must live in a directory of its own, holding only the wallet,
+    /// its `.bak` backup and temporary files; a shared directory such as
+    /// `$HOME` is refused rather than opened up for writing.
+    pub fn for_wallet(wallet_path: impl AsRef<Path>) -> Result<Self, SandboxError> {
+        let wallet_path = wallet_path.as_ref();
+        let wallet_dir = match wallet_path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let name = wallet_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if let Some(other) = foreign_entry(&wallet_dir, &name) {
+            return Err(SandboxError::Failed(format!(
+                "the wallet must be in a directory of its own, but {} also holds '{}'; move it, e.g. to ~/.wallet/{}, and set {}",
+                wallet_dir.display(),
+                other,
+                name,
+                crate::WALLET_FILE_ENV
+            )));
+        }
+
+        let mut sandbox = Sandbox {
+            read_write: vec![wallet_dir],
+            read_only: SYSTEM_DIRS.iter().map(PathBuf::from).collect(),
+        };
+        sandbox.read_write.extend(DEVICES.iter().map(PathBuf::from));
+        if let Some(dir) = config_dir() {
+            sandbox.read_only.push(dir);
+        }
+        Ok(sandbox)
+    }
+
+    /// Also allows writing under `path`, a directory tree.
+    pub fn allow_write(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.read_write.push(path.into());
+        self
+    }
+
+    /// Also allows reading `path`, a file or a directory tree.
+    pub fn allow_read(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.read_only.push(path.into());
+        self
+    }
+
+    /// Restricts the current process. This cannot be undone.
+    pub fn apply(&self) -> Result<(), SandboxError> {
+        #[cfg(target_os = "linux")]
+        {
+            self.restrict_filesystem()?;
+            deny_network()
+        }
+        #[cfg(not(target_os = "linux"))]
+        Err(SandboxError::Unsupported(
+            "the sandbox is only available on Linux".to_string(),
+        ))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn restrict_filesystem(&self) -> Result<(), SandboxError> {
+        use landlock::{
+            ABI, Access, AccessFs, Ruleset, RulesetAttr, RulesetCreatedAttr, RulesetStatus,
+            path_beneath_rules,
+        };
+
+        // Rules for missing paths would fail; there is nothing to allow there.
+        let existing = |paths: &[PathBuf]| -> Vec<PathBuf> {
+            paths.iter().filter(|path| path.exists()).cloned().collect()
+        };
+        let abi = ABI::V5;
+        let status = Ruleset::default()
+            .handle_access(AccessFs::from_all(abi))
+            .and_then(|ruleset| ruleset.create())
+            .and_then(|ruleset| {
+                ruleset.add_rules(path_beneath_rules(
+                    existing(&self.read_write),
+                    AccessFs::from_all(abi),
+                ))
+            })
+            .and_then(|ruleset| {
+                ruleset.add_rules(path_beneath_rules(
+                    existing(&self.read_only),
+                    AccessFs::from_read(abi),
+                ))
+            })
+            .and_then(|ruleset| ruleset.restrict_self())
+            .map_err(|e| SandboxError::Failed(format!("Landlock: {}", e)))?;
+
+        if status.ruleset == RulesetStatus::NotEnforced {
+            return Err(SandboxError::Unsupported(
+                "this kernel does not support Landlock (Linux 5.13 or newer with Landlock enabled)"
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The first entry of `dir` that is not the wallet `name`, its backup or
+/// one of its temporary files.
+fn foreign_entry(dir: &Path, name: &str) -> Option<String> {
+    let backup = format!("{}.bak", name);
+    let temp = format!(".{}.tmp-", name);
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => return Some(e.to_string()),
+    };
+    entries
+        .map(|entry| match entry {
+            Ok(entry) => entry.file_name().to_string_lossy().into_owned(),
+            Err(e) => e.to_string(),
+        })
+        .find(|entry| entry != name && *entry != backup && !entry.starts_with(&temp))
+}
+
+/// `$XDG_CONFIG_HOME/wallet`, or `~/.config/wallet`.
+fn config_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("wallet")),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("wallet")),
+    }
+}
+
+/// Installs a seccomp filter failing `socket(2)` with `EACCES` for every
+/// address family but `AF_UNIX`.
+#[cfg(target_os = "linux")]
+fn deny_network() -> Result<(), SandboxError> {
+    use seccompiler::{
+        BpfProgram, SeccompAction, SeccompCmpArgLen, SeccompCmpOp, SeccompCondition, SeccompFilter,
+        SeccompRule, TargetArch,
+    };
+
+    let arch = TargetArch::try_from(env::consts::ARCH).map_err(|_| {
+        SandboxError::Unsupported(format!(
+            "seccomp filters are not supported on {}",
+            env::consts::ARCH
+        ))
+    })?;
+    let failed = |e: seccompiler::BackendError| SandboxError::Failed(format!("seccomp: {}", e));
+    let not_unix = SeccompCondition::new(
+        0,
+        SeccompCmpArgLen::Dword,
+        SeccompCmpOp::Ne,
+        libc::AF_UNIX as u64,
+    )
+    .map_err(failed)?;
+    let rules = [(
+        libc::SYS_socket,
+        vec![SeccompRule::new(vec![not_unix]).map_err(failed)?],
+    )];
+    let filter = SeccompFilter::new(
+        rules.into_iter().collect(),
+        SeccompAction::Allow,
+        SeccompAction::Errno(libc::EACCES as u32),
+        arch,
+    )
+    .map_err(failed)?;
+    let program: BpfProgram = filter.try_into().map_err(failed)?;
+    seccompiler::apply_filter_all_threads(&program)
+        .map_err(|e| SandboxError::Failed(format!("seccomp: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_wallet_allows_only_a_dedicated_directory() {
+        let dir = env::temp_dir().join(format!("wallet-sandbox-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let wallet = dir.join("wallet.txt");
+        fs::write(&wallet, b"wallet").unwrap();
+        fs::write(dir.join("wallet.txt.bak"), b"backup").unwrap();
+
+        let mut sandbox = Sandbox::for_wallet(&wallet).unwrap();
+        assert_eq!(sandbox.read_write[0], dir);
+        assert!(sandbox.read_write.contains(&PathBuf::from("/dev/tty")));
+        sandbox.allow_read("/srv/pwned.idx");
+        assert!(sandbox.read_only.contains(&PathBuf::from("/srv/pwned.idx")));
+
+        // Another file means the directory is shared, e.g. `$HOME`.
+        fs::write(dir.join(".bashrc"), b"").unwrap();
+        let err = Sandbox::for_wallet(&wallet).unwrap_err();
+        assert!(err.to_string().contains(".bashrc"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,187 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, ops::Deref};
+use zeroize::Zeroize;
+
+use crate::harden;
+
+/// A string holding secret material, such as a password or entry value.
+///
+/// The contents are pinned in RAM, overwritten when the value is dropped,
+/// and never printed by `Debug`. Derefs to `str` so it can be passed wherever a
+/// `&str` is expected.
+#[derive(Default, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct SecretString(String);
+
+impl SecretString {
+    pub fn new(value: String) -> Self {
+        // Best effort: a failure is counted and reported by `wallet doctor`.
+        let _ = harden::lock(value.as_bytes());
+        SecretString(value)
+    }
+
+    /// Replaces the contents, wiping the previous value.
+    pub fn replace(&mut self, value: String) {
+        let _ = harden::lock(value.as_bytes());
+        self.0.zeroize();
+        self.0 = value;
+    }
+}
+
+impl Clone for SecretString {
+    fn clone(&self) -> Self {
+        SecretString::new(self.0.clone())
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretString {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(SecretString::new)
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(value: String) -> Self {
+        SecretString::new(value)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(value: &str) -> Self {
+        SecretString::new(value.to_string())
+    }
+}
+
+impl Deref for SecretString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for SecretString {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for SecretString {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretString(<redacted>)")
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// A byte buffer holding secret material, such as a decrypted wallet.
+///
+/// Pinned in RAM and wiped on drop like [`SecretString`].
+#[derive(Default, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct SecretBytes(Vec<u8>);
+
+impl SecretBytes {
+    pub fn new(value: Vec<u8>) -> Self {
+        let _ = harden::lock(&value);
+        SecretBytes(value)
+    }
+
+    /// Converts to a [`SecretString`] without copying, failing if the bytes
+    /// are not UTF-8. The buffer is wiped on failure too.
+    pub fn into_string(mut self) -> Option<SecretString> {
+        let bytes = std::mem::take(&mut self.0);
+        match String::from_utf8(bytes) {
+            // Same buffer, so it is already locked.
+            Ok(value) => Some(SecretString(value)),
+            Err(e) => {
+                e.into_bytes().zeroize();
+                None
+            }
+        }
+    }
+}
+
+impl Clone for SecretBytes {
+    fn clone(&self) -> Self {
+        SecretBytes::new(self.0.clone())
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretBytes {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(SecretBytes::new)
+    }
+}
+
+impl From<Vec<u8>> for SecretBytes {
+    fn from(value: Vec<u8>) -> Self {
+        SecretBytes::new(value)
+    }
+}
+
+impl Deref for SecretBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl PartialEq<[u8]> for SecretBytes {
+    fn eq(&self, other: &[u8]) -> bool {
+        self.0 == other
+    }
+}
+
+impl<const N: usize> PartialEq<&[u8; N]> for SecretBytes {
+    fn eq(&self, other: &&[u8; N]) -> bool {
+        self.0 == other[..]
+    }
+}
+
+impl fmt::Debug for SecretBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretBytes(<{} bytes redacted>)", self.0.len())
+    }
+}
+
+impl Drop for SecretBytes {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debug_is_redacted() {
+        let password = SecretString::from("hunter2");
+        assert_eq!(format!("{:?}", password), "SecretString(<redacted>)");
+        assert_eq!(&*password, "hunter2");
+
+        let bytes = SecretBytes::new(b"key:value".to_vec());
+        assert!(!format!("{:?}", bytes).contains("key"));
+        assert_eq!(bytes.into_string().unwrap(), "key:value");
+    }
+
+    #[test]
+    fn test_serializes_as_plain_string() {
+        let secret = SecretString::from("hunter2");
+        assert_eq!(serde_json::to_string(&secret).unwrap(), "\"hunter2\"");
+        let parsed: SecretString = serde_json::from_str("\"hunter2\"").unwrap();
+        assert_eq!(parsed, secret);
+    }
+}
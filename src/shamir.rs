@@ -0,0 +1,127 @@
+use rand::Rng;
+use zeroize::Zeroizing;
+
+use crate::secret::SecretBytes;
+
+/// Splits `secret` into `count` shares so that any `threshold` of them
+/// recover it and fewer reveal nothing about it.
+///
+/// Each byte of the secret is the constant term of its own random polynomial
+/// of degree `threshold - 1` over GF(256); share `x` holds the values of all
+/// polynomials at `x`. Returns `(x, values)` pairs for `x` in `1..=count`.
+///
+/// # Panics
+///
+/// If `threshold` is zero or greater than `count`.
+pub fn split(
+    secret: &[u8],
+    threshold: u8,
+    count: u8,
+    rng: &mut impl Rng,
+) -> Vec<(u8, SecretBytes)> {
+    assert!(threshold >= 1 && threshold <= count);
+    let mut shares: Vec<(u8, Vec<u8>)> = (1..=count)
+        .map(|x| (x, Vec::with_capacity(secret.len())))
+        .collect();
+    let mut coefficients = Zeroizing::new(vec![0u8; threshold as usize]);
+    for &byte in secret {
+        coefficients[0] = byte;
+        rng.fill(&mut coefficients[1..]);
+        for (x, values) in &mut shares {
+            values.push(evaluate(&coefficients, *x));
+        }
+    }
+    shares
+        .into_iter()
+        .map(|(x, values)| (x, SecretBytes::new(values)))
+        .collect()
+}
+
+/// Recovers the secret from `(x, values)` shares by Lagrange interpolation
+/// at zero. The shares must have distinct, non-zero `x` and equal lengths;
+/// with fewer shares than the threshold the result is meaningless.
+pub fn combine(shares: &[(u8, &[u8])]) -> SecretBytes {
+    let len = shares.first().map_or(0, |(_, values)| values.len());
+    let mut secret = vec![0u8; len];
+    for (i, (xi, values)) in shares.iter().enumerate() {
+        // Lagrange basis polynomial for share i, evaluated at zero.
+        let mut basis = 1;
+        for (j, (xj, _)) in shares.iter().enumerate() {
+            if i != j {
+                basis = mul(basis, mul(*xj, inverse(xj ^ xi)));
+            }
+        }
+        for (byte, value) in secret.iter_mut().zip(values.iter()) {
+            *byte ^= mul(*value, basis);
+        }
+    }
+    SecretBytes::new(secret)
+}
+
+/// Evaluates the polynomial with the given coefficients (constant term
+/// first) at `x` using Horner's rule.
+fn evaluate(coefficients: &[u8], x: u8) -> u8 {
+    coefficients
+        .iter()
+        .rev()
+        .fold(0, |acc, &coefficient| mul(acc, x) ^ coefficient)
+}
+
+/// Multiplication in GF(256) modulo the AES polynomial x^8 + x^4 + x^3 + x + 1.
+/// Runs in constant time so the secret bytes do not leak through timing.
+fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    for _ in 0..8 {
+        product ^= a & (b & 1).wrapping_neg();
+        let carry = (a >> 7).wrapping_neg();
+        a = (a << 1) ^ (carry & 0x1b);
+        b >>= 1;
+    }
+    product
+}
+
+/// Multiplicative inverse in GF(256), as a^254.
+fn inverse(a: u8) -> u8 {
+    let mut result = 1;
+    let mut power = a;
+    for bit in 0..8 {
+        if 254 & (1 << bit) != 0 {
+            result = mul(result, power);
+        }
+        power = mul(power, power);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{TryRngCore, rngs::OsRng};
+
+    #[test]
+    fn test_field_arithmetic() {
+        // Known product from the AES specification.
+        assert_eq!(mul(0x57, 0x83), 0xc1);
+        for a in 1..=255u8 {
+            assert_eq!(mul(a, inverse(a)), 1);
+        }
+    }
+
+    #[test]
+    fn test_any_threshold_of_shares_recovers_the_secret() {
+        let secret = b"sixteen byte key";
+        let shares = split(secret, 3, 5, &mut OsRng.unwrap_err());
+        assert_eq!(shares.len(), 5);
+
+        let pick = |indices: &[usize]| -> Vec<(u8, &[u8])> {
+            indices
+                .iter()
+                .map(|&i| (shares[i].0, &shares[i].1[..]))
+                .collect()
+        };
+        assert_eq!(combine(&pick(&[0, 1, 2])), secret);
+        assert_eq!(combine(&pick(&[4, 2, 0])), secret);
+        assert_eq!(combine(&pick(&[1, 2, 3, 4])), secret);
+        assert_ne!(combine(&pick(&[0, 1])), secret);
+    }
+}
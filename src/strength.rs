@@ -0,0 +1,636 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use crate::passphrase::Wordlist;
+
+/// Frequently used passwords, most common first.
+const COMMON_PASSWORDS: &str = include_str!("wordlists/common_passwords.txt");
+
+/// Keyboard rows used to spot straight keyboard patterns like `qwerty`.
+const KEYBOARD_ROWS: [&str; 5] = [
+    "1234567890",
+    "qwertyuiop",
+    "asdfghjkl",
+    "zxcvbnm",
+    "!@#$%^&*()",
+];
+
+/// Common "l33t" substitutions and the letters they stand for.
+const L33T: [(char, &str); 10] = [
+    ('4', "a"),
+    ('@', "a"),
+    ('8', "b"),
+    ('3', "e"),
+    ('1', "il"),
+    ('!', "i"),
+    ('0', "o"),
+    ('$', "s"),
+    ('5', "s"),
+    ('7', "t"),
+];
+
+/// Longest prefix of a secret that is analysed; anything longer is strong.
+const MAX_ANALYSED_LEN: usize = 100;
+
+/// Longest dictionary word or user input looked for.
+const MAX_WORD_LEN: usize = 32;
+
+/// Score (0-4) below which a value is reported as weak.
+pub const WEAK_SCORE: u8 = 3;
+
+/// Kind of guessable pattern found in a secret.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    CommonPassword,
+    DictionaryWord,
+    UserInput,
+    Keyboard,
+    Repeat,
+    Sequence,
+    Date,
+}
+
+/// Result of estimating how hard a secret is to guess.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Strength {
+    /// 0 (trivially guessable) to 4 (very hard to guess).
+    pub score: u8,
+    /// Estimated number of guesses needed, as a power of ten.
+    pub guesses_log10: f64,
+    /// Patterns that were used to guess the secret, in order.
+    pub patterns: Vec<Pattern>,
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+}
+
+impl Strength {
+    pub fn is_weak(&self) -> bool {
+        self.score < WEAK_SCORE
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self.score {
+            0 => "very weak",
+            1 => "weak",
+            2 => "fair",
+            3 => "strong",
+            _ => "very strong",
+        }
+    }
+}
+
+impl std::fmt::Display for Strength {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (score {}/4, ~10^{:.0} guesses)",
+            self.label(),
+            self.score,
+            self.guesses_log10
+        )?;
+        if let Some(warning) = &self.warning {
+            write!(f, ": {}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+/// A guessable span `chars[start..end]`.
+#[derive(Debug, Clone, Copy)]
+struct Match {
+    start: usize,
+    end: usize,
+    guesses_log10: f64,
+    pattern: Pattern,
+    l33t: bool,
+}
+
+/// Estimates the strength of `secret` in the style of zxcvbn.
+///
+/// The secret is split into the cheapest sequence of guessable patterns
+/// (common passwords, dictionary words, `user_inputs` such as the entry
+/// name, keyboard rows, repeats, sequences and dates, with l33t
+/// substitutions undone) and brute-forced characters.
+pub fn estimate(secret: &str, user_inputs: &[&str]) -> Strength {
+    let chars: Vec<char> = secret.chars().take(MAX_ANALYSED_LEN).collect();
+    if chars.is_empty() {
+        return Strength {
+            score: 0,
+            guesses_log10: 0.0,
+            patterns: Vec::new(),
+            warning: Some("The value is empty".to_string()),
+            suggestions: vec!["Use a few words, avoid common phrases".to_string()],
+        };
+    }
+
+    let matches = find_matches(&chars, user_inputs);
+    let (guesses_log10, path) = cheapest_path(&chars, &matches);
+    let score = score(guesses_log10);
+    let (warning, suggestions) = feedback(score, &path, chars.len());
+
+    Strength {
+        score,
+        guesses_log10,
+        patterns: path.iter().map(|m| m.pattern).collect(),
+        warning,
+        suggestions,
+    }
+}
+
+fn score(guesses_log10: f64) -> u8 {
+    match guesses_log10 {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => 4,
+    }
+}
+
+/// Dynamic programming over positions: the cheapest way to cover the
+/// whole secret with matches and single brute-forced characters.
+fn cheapest_path(chars: &[char], matches: &[Match]) -> (f64, Vec<Match>) {
+    let n = chars.len();
+    let mut best = vec![f64::INFINITY; n + 1];
+    let mut via: Vec<Option<Match>> = vec![None; n + 1];
+    best[0] = 0.0;
+
+    for end in 1..=n {
+        let brute = best[end - 1] + cardinality(chars[end - 1]).log10();
+        if brute < best[end] {
+            best[end] = brute;
+            via[end] = None;
+        }
+        for m in matches.iter().filter(|m| m.end == end) {
+            let cost = best[m.start] + m.guesses_log10;
+            if cost < best[end] {
+                best[end] = cost;
+                via[end] = Some(*m);
+            }
+        }
+    }
+
+    let mut path = Vec::new();
+    let mut end = n;
+    while end > 0 {
+        match via[end] {
+            Some(m) => {
+                path.push(m);
+                end = m.start;
+            }
+            None => end -= 1,
+        }
+    }
+    path.reverse();
+    (best[n], path)
+}
+
+fn cardinality(c: char) -> f64 {
+    if c.is_ascii_lowercase() || c.is_ascii_uppercase() {
+        26.0
+    } else if c.is_ascii_digit() {
+        10.0
+    } else if c.is_ascii() {
+        33.0
+    } else {
+        100.0
+    }
+}
+
+fn find_matches(chars: &[char], user_inputs: &[&str]) -> Vec<Match> {
+    let mut matches = dictionary_matches(chars, user_inputs);
+    matches.extend(keyboard_matches(chars));
+    matches.extend(repeat_matches(chars));
+    matches.extend(sequence_matches(chars));
+    matches.extend(date_matches(chars));
+    matches
+}
+
+/// Ranked dictionaries: common passwords by popularity and the bundled
+/// wordlist. Built once per process.
+fn dictionary() -> &'static HashMap<String, (f64, Pattern)> {
+    static DICTIONARY: OnceLock<HashMap<String, (f64, Pattern)>> = OnceLock::new();
+    DICTIONARY.get_or_init(|| {
+        let mut ranks = HashMap::new();
+        let wordlist = Wordlist::bundled();
+        let rank = wordlist.len() as f64;
+        for word in wordlist.words() {
+            ranks.insert(word.clone(), (rank, Pattern::DictionaryWord));
+        }
+        for (rank, word) in COMMON_PASSWORDS.lines().enumerate() {
+            ranks.insert(
+                word.to_string(),
+                ((rank + 1) as f64, Pattern::CommonPassword),
+            );
+        }
+        ranks
+    })
+}
+
+fn dictionary_matches(chars: &[char], user_inputs: &[&str]) -> Vec<Match> {
+    let user_inputs: Vec<String> = user_inputs
+        .iter()
+        .map(|word| word.to_lowercase())
+        .filter(|word| word.chars().count() >= 3)
+        .collect();
+    let lookup = |candidate: &String| {
+        if user_inputs.contains(candidate) {
+            Some((1.0, Pattern::UserInput))
+        } else {
+            dictionary().get(candidate).copied()
+        }
+    };
+
+    let lower: Vec<char> = chars.iter().flat_map(|c| c.to_lowercase()).collect();
+    if lower.len() != chars.len() {
+        return Vec::new();
+    }
+
+    let mut matches = Vec::new();
+    for start in 0..chars.len() {
+        for end in start + 1..=chars.len().min(start + MAX_WORD_LEN) {
+            let span = &lower[start..end];
+            for (candidate, substitutions) in unl33t(span) {
+                let Some((rank, pattern)) = lookup(&candidate) else {
+                    continue;
+                };
+                // Short words hide everywhere; only count them as whole secrets.
+                if end - start < 3 && (start, end) != (0, chars.len()) {
+                    continue;
+                }
+                let guesses =
+                    rank * uppercase_variations(&chars[start..end]) * 2f64.powi(substitutions);
+                matches.push(Match {
+                    start,
+                    end,
+                    guesses_log10: guesses.max(1.0).log10(),
+                    pattern,
+                    l33t: substitutions > 0,
+                });
+            }
+        }
+    }
+    matches
+}
+
+/// All readings of `span` with l33t characters replaced by letters, along
+/// with the number of substitutions made.
+fn unl33t(span: &[char]) -> Vec<(String, i32)> {
+    let mut readings = vec![(String::new(), 0)];
+    for &c in span {
+        match L33T.iter().find(|(l33t, _)| *l33t == c) {
+            Some((_, letters)) => {
+                let mut next = Vec::new();
+                for (reading, subs) in &readings {
+                    next.push((format!("{}{}", reading, c), *subs));
+                    for letter in letters.chars() {
+                        next.push((format!("{}{}", reading, letter), subs + 1));
+                    }
+                }
+                // Keep the search bounded for long runs of digits/symbols.
+                next.truncate(64);
+                readings = next;
+            }
+            None => readings.iter_mut().for_each(|(reading, _)| reading.push(c)),
+        }
+    }
+    readings
+}
+
+/// Extra guesses needed for capitalization beyond all-lowercase.
+fn uppercase_variations(word: &[char]) -> f64 {
+    let upper = word.iter().filter(|c| c.is_uppercase()).count();
+    let lower = word.iter().filter(|c| c.is_lowercase()).count();
+    if upper == 0 {
+        1.0
+    } else if lower == 0
+        || (upper == 1 && (word[0].is_uppercase() || word[word.len() - 1].is_uppercase()))
+    {
+        2.0
+    } else {
+        2f64.powi(upper.min(lower) as i32 + 1)
+    }
+}
+
+fn keyboard_matches(chars: &[char]) -> Vec<Match> {
+    let lower: Vec<char> = chars.iter().map(|c| c.to_ascii_lowercase()).collect();
+    let position = |c: char| {
+        KEYBOARD_ROWS
+            .iter()
+            .enumerate()
+            .find_map(|(row, keys)| keys.chars().position(|k| k == c).map(|col| (row, col)))
+    };
+
+    let mut matches = Vec::new();
+    let mut start = 0;
+    while start < lower.len() {
+        let mut end = start + 1;
+        let mut turns = 0;
+        let mut direction = 0i32;
+        while end < lower.len() {
+            let (Some((row_a, col_a)), Some((row_b, col_b))) =
+                (position(lower[end - 1]), position(lower[end]))
+            else {
+                break;
+            };
+            let step = col_b as i32 - col_a as i32;
+            if row_a != row_b || step.abs() != 1 {
+                break;
+            }
+            if step != direction {
+                turns += 1;
+                direction = step;
+            }
+            end += 1;
+        }
+        let len = end - start;
+        if len >= 3 {
+            let guesses = 100.0 * (len * len) as f64 * 2f64.powi(turns - 1);
+            matches.push(Match {
+                start,
+                end,
+                guesses_log10: guesses.log10(),
+                pattern: Pattern::Keyboard,
+                l33t: false,
+            });
+        }
+        start = end.max(start + 1);
+    }
+    matches
+}
+
+/// Runs of a repeated unit, such as `abcabcabc`. The unit is scored on its
+/// own by [`unit_guesses_log10`], which is cheap and memoized, so a long
+/// repetitive value costs at most `len * MAX_WORD_LEN` lookups.
+fn repeat_matches(chars: &[char]) -> Vec<Match> {
+    let mut unit_guesses: HashMap<&[char], f64> = HashMap::new();
+    let mut matches = Vec::new();
+    for start in 0..chars.len() {
+        for unit_len in 1..=((chars.len() - start) / 2).min(MAX_WORD_LEN) {
+            let unit = &chars[start..start + unit_len];
+            let mut count = 1;
+            while start + (count + 1) * unit_len <= chars.len()
+                && &chars[start + count * unit_len..start + (count + 1) * unit_len] == unit
+            {
+                count += 1;
+            }
+            let end = start + count * unit_len;
+            if count >= 2 && end - start >= 3 {
+                let guesses = *unit_guesses
+                    .entry(unit)
+                    .or_insert_with(|| unit_guesses_log10(unit));
+                matches.push(Match {
+                    start,
+                    end,
+                    guesses_log10: guesses + (count as f64).log10(),
+                    pattern: Pattern::Repeat,
+                    l33t: false,
+                });
+            }
+        }
+    }
+    matches
+}
+
+/// Guesses for the unit of a repeat: its dictionary rank if it is a known
+/// word, and brute force otherwise. Deliberately not a full [`estimate`],
+/// whose recursion through repeats of repeats explodes on long values.
+fn unit_guesses_log10(unit: &[char]) -> f64 {
+    let lower: String = unit.iter().flat_map(|c| c.to_lowercase()).collect();
+    let brute: f64 = unit.iter().map(|&c| cardinality(c).log10()).sum();
+    match dictionary().get(&lower) {
+        Some((rank, _)) => (rank * uppercase_variations(unit))
+            .max(1.0)
+            .log10()
+            .min(brute),
+        None => brute,
+    }
+}
+
+fn sequence_matches(chars: &[char]) -> Vec<Match> {
+    let class = |c: char| {
+        if c.is_ascii_lowercase() {
+            1
+        } else if c.is_ascii_uppercase() {
+            2
+        } else if c.is_ascii_digit() {
+            3
+        } else {
+            0
+        }
+    };
+
+    let mut matches = Vec::new();
+    let mut start = 0;
+    while start + 1 < chars.len() {
+        let delta = chars[start + 1] as i32 - chars[start] as i32;
+        let mut end = start + 1;
+        while end < chars.len()
+            && delta.abs() == 1
+            && class(chars[end]) != 0
+            && class(chars[end]) == class(chars[start])
+            && chars[end] as i32 - chars[end - 1] as i32 == delta
+        {
+            end += 1;
+        }
+        if end - start >= 3 {
+            let first = chars[start];
+            let base: f64 = if "aAzZ019".contains(first) {
+                4.0
+            } else if first.is_ascii_digit() {
+                10.0
+            } else {
+                26.0
+            };
+            let direction = if delta < 0 { 2.0 } else { 1.0 };
+            matches.push(Match {
+                start,
+                end,
+                guesses_log10: (base * (end - start) as f64 * direction).log10(),
+                pattern: Pattern::Sequence,
+                l33t: false,
+            });
+            start = end - 1;
+        } else {
+            start += 1;
+        }
+    }
+    matches
+}
+
+/// Years (1900-2039) and day/month/year dates, with or without separators.
+fn date_matches(chars: &[char]) -> Vec<Match> {
+    let mut matches = Vec::new();
+    for start in 0..chars.len() {
+        for end in start + 4..=chars.len().min(start + 10) {
+            let span: String = chars[start..end].iter().collect();
+            let guesses = if is_year(&span) {
+                140.0
+            } else if is_date(&span) {
+                365.0 * 140.0
+            } else {
+                continue;
+            };
+            matches.push(Match {
+                start,
+                end,
+                guesses_log10: f64::log10(guesses),
+                pattern: Pattern::Date,
+                l33t: false,
+            });
+        }
+    }
+    matches
+}
+
+fn is_year(s: &str) -> bool {
+    s.len() == 4 && s.parse::<u32>().is_ok_and(|y| (1900..=2039).contains(&y))
+}
+
+fn is_date(s: &str) -> bool {
+    let parts: Vec<&str> = s.split(['/', '-', '.', ' ']).collect();
+    let numbers: Vec<&str> = if parts.len() == 3 {
+        parts
+    } else if parts.len() == 1 && s.chars().all(|c| c.is_ascii_digit()) {
+        match s.len() {
+            6 => vec![&s[0..2], &s[2..4], &s[4..6]],
+            8 if is_year(&s[0..4]) => vec![&s[0..4], &s[4..6], &s[6..8]],
+            8 => vec![&s[0..2], &s[2..4], &s[4..8]],
+            _ => return false,
+        }
+    } else {
+        return false;
+    };
+
+    let Ok(values) = numbers
+        .iter()
+        .map(|n| n.parse::<u32>())
+        .collect::<Result<Vec<u32>, _>>()
+    else {
+        return false;
+    };
+    let day_month = |a: u32, b: u32| (1..=31).contains(&a) && (1..=12).contains(&b);
+    let year = |y: &str, v: u32| (y.len() == 2) || (y.len() == 4 && (1900..=2039).contains(&v));
+
+    // d/m/y, m/d/y or y/m/d
+    (year(numbers[2], values[2])
+        && (day_month(values[0], values[1]) || day_month(values[1], values[0])))
+        || (numbers[0].len() == 4 && year(numbers[0], values[0]) && day_month(values[2], values[1]))
+}
+
+fn feedback(score: u8, path: &[Match], len: usize) -> (Option<String>, Vec<String>) {
+    if score >= WEAK_SCORE {
+        return (None, Vec::new());
+    }
+
+    let mut suggestions = vec!["Add another word or two. Uncommon words are better.".to_string()];
+    let longest = path.iter().max_by_key(|m| m.end - m.start);
+    let warning = longest.map(|m| {
+        let whole = m.end - m.start == len;
+        match m.pattern {
+            Pattern::CommonPassword if whole => "This is a very common password",
+            Pattern::CommonPassword => "This contains a very common password",
+            Pattern::DictionaryWord if whole => "A word by itself is easy to guess",
+            Pattern::DictionaryWord => "Common words are easy to guess",
+            Pattern::UserInput => "Names related to the entry are easy to guess",
+            Pattern::Keyboard => "Straight rows of keys are easy to guess",
+            Pattern::Repeat => "Repeats like \"aaa\" or \"abcabc\" are easy to guess",
+            Pattern::Sequence => "Sequences like abc or 6543 are easy to guess",
+            Pattern::Date => "Dates and years are often easy to guess",
+        }
+        .to_string()
+    });
+
+    if path.iter().any(|m| m.l33t) {
+        suggestions.push(
+            "Predictable substitutions like '@' instead of 'a' don't help very much".to_string(),
+        );
+    }
+    match longest.map(|m| m.pattern) {
+        Some(Pattern::Keyboard) => {
+            suggestions.push("Use a longer keyboard pattern with more turns".to_string())
+        }
+        Some(Pattern::Repeat) => {
+            suggestions.push("Avoid repeated words and characters".to_string())
+        }
+        Some(Pattern::Sequence) => suggestions.push("Avoid sequences".to_string()),
+        Some(Pattern::Date) => {
+            suggestions.push("Avoid dates and years that are associated with you".to_string())
+        }
+        _ => {}
+    }
+    if len < 12 {
+        suggestions.push("Use a longer value; 12 or more characters".to_string());
+    }
+
+    (warning, suggestions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_common_passwords_are_very_weak() {
+        for password in ["password", "123456", "qwerty", "P@ssw0rd", "letmein"] {
+            let strength = estimate(password, &[]);
+            assert_eq!(strength.score, 0, "{}: {:?}", password, strength);
+            assert!(strength.warning.is_some());
+        }
+    }
+
+    #[test]
+    fn test_patterns_are_detected() {
+        assert!(
+            estimate("asdfghjk", &[])
+                .patterns
+                .contains(&Pattern::Keyboard)
+        );
+        assert!(
+            estimate("zzzzzzzz", &[])
+                .patterns
+                .contains(&Pattern::Repeat)
+        );
+        assert!(
+            estimate("abcdefgh", &[])
+                .patterns
+                .contains(&Pattern::Sequence)
+        );
+        assert!(
+            estimate("xq19870412", &[])
+                .patterns
+                .contains(&Pattern::Date)
+        );
+        assert!(
+            estimate("github2024", &["github"])
+                .patterns
+                .contains(&Pattern::UserInput)
+        );
+    }
+
+    #[test]
+    fn test_random_and_passphrase_secrets_are_strong() {
+        assert_eq!(estimate("x7#Qm9!vL2@pR4zW", &[]).score, 4);
+        assert!(!estimate("correct-horse-battery-staple-orbit", &[]).is_weak());
+    }
+
+    #[test]
+    fn test_long_repetitive_values_are_fast() {
+        let started = std::time::Instant::now();
+        for secret in ["1".repeat(100), "ab".repeat(50), "1234567890".repeat(10)] {
+            assert!(estimate(&secret, &[]).is_weak(), "{}", secret);
+        }
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_l33t_is_flagged() {
+        let strength = estimate("m0nk3y", &[]);
+        assert!(strength.is_weak());
+        assert!(
+            strength
+                .suggestions
+                .iter()
+                .any(|s| s.contains("substitutions"))
+        );
+    }
+}